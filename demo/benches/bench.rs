@@ -12,45 +12,208 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use criterion::{black_box, criterion_group, criterion_main, Criterion};
+//! The stages of the pipeline are measured separately, so that we can see where the time goes:
+//!
+//! - `synthesis`: running gnark-circuit-gen and importing its R1CS into snarkVM, one circuit after
+//!   the other, reported per instance,
+//! - `witness`: generating the assignments of a batch concurrently, reported per instance,
+//! - `prove_batch`: the Varuna prover alone, on assignments generated beforehand, with and
+//!   without zero knowledge,
+//! - `verify_batch`: the Varuna verifier alone.
+//!
+//! Batches have 1, 8, 64 and 256 instances, as long as their assignments fit in
+//! [`MEMORY_BUDGET`]: 256 assignments of `test3` do not. The `proof_and_verify` group keeps the
+//! end to end measure of the competition: compiling, proving and verifying a batch of 64
+//! signatures.
+
+use criterion::measurement::WallTime;
+use criterion::{
+    black_box, criterion_group, criterion_main, BenchmarkGroup, BenchmarkId, Criterion,
+    SamplingMode, Throughput,
+};
+use demo::api::{self, ProveJob, ProverConfig};
 use demo::TestCase;
-use log::{info, LevelFilter};
+use hap_verifier::{Encoding, VarunaHidingMode, VarunaNonHidingMode};
+use log::LevelFilter;
+use snarkvm_algorithms::{
+    polycommit::kzg10::UniversalParams,
+    snark::varuna::{CircuitProvingKey, SNARKMode},
+};
+use snarkvm_circuit::Circuit;
+use snarkvm_curves::bls12_377::Bls12_377;
+
+const BATCH_SIZES: [u32; 4] = [1, 8, 64, 256];
 
-fn criterion_benchmark(c: &mut Criterion) {
-    env_logger::builder()
-        .filter_level(LevelFilter::Info)
+/// Memory for the assignments of a batch, which are all held until the proof is computed.
+const MEMORY_BUDGET: usize = 16 << 30;
+
+/// The batch sizes benchmarked for `test_case`, those whose assignments fit in the budget, see
+/// [`api::estimate_assignment_size`]. A batch of one is always benchmarked.
+fn batch_sizes(test_case: TestCase) -> Vec<u32> {
+    let (retained, _) = api::estimate_assignment_size(&api::circuit_stats(test_case));
+    BATCH_SIZES
+        .into_iter()
+        .filter(|batch_num| *batch_num == 1 || retained * *batch_num as usize <= MEMORY_BUDGET)
+        .collect()
+}
+
+fn init_logger() {
+    let _ = env_logger::builder()
+        .filter_level(LevelFilter::Warn)
         .is_test(true)
-        .try_init()
-        .unwrap();
+        .try_init();
+}
 
-    let mut group = c.benchmark_group("proof_and_verify");
+fn init() -> UniversalParams<Bls12_377> {
+    init_logger();
+    // The parameters are extended when compiling.
+    api::setup::<VarunaHidingMode>(1000, 1000, 1000).unwrap()
+}
+
+/// Slow benchmarks: few samples, and one iteration per sample.
+fn slow_group<'a>(c: &'a mut Criterion, name: &str) -> BenchmarkGroup<'a, WallTime> {
+    let mut group = c.benchmark_group(name);
+    group.sample_size(10).sampling_mode(SamplingMode::Flat);
     group
-        .sample_size(10)
-        .sampling_mode(criterion::SamplingMode::Flat); // for slow benchmarks
+}
+
+fn job<SM: SNARKMode>(
+    test_case: TestCase,
+    pk: &CircuitProvingKey<Bls12_377, SM>,
+    batch_num: u32,
+) -> ProveJob<SM> {
+    ProveJob {
+        test_case,
+        pk,
+        batch_num,
+        signatures: None,
+        encoding: Encoding::default(),
+    }
+}
+
+fn bench_synthesis(c: &mut Criterion) {
+    init_logger();
+    let mut group = slow_group(c, "synthesis");
+    for test_case in TestCase::ALL {
+        for batch_num in batch_sizes(test_case) {
+            group.throughput(Throughput::Elements(batch_num as u64));
+            group.bench_with_input(
+                BenchmarkId::new(test_case.name(), batch_num),
+                &batch_num,
+                |b, batch_num| {
+                    b.iter(|| {
+                        /* one at a time, only one assignment is in memory */
+                        for _ in 0..*batch_num {
+                            black_box(api::synthesize(black_box(test_case)));
+                        }
+                    })
+                },
+            );
+        }
+    }
+}
+
+fn bench_witness(c: &mut Criterion) {
+    let urs = init();
+    let config = ProverConfig::default();
+    let mut group = slow_group(c, "witness");
+    for test_case in TestCase::ALL {
+        let (pk, _) = api::compile(test_case, &urs).unwrap();
+        for batch_num in batch_sizes(test_case) {
+            group.throughput(Throughput::Elements(batch_num as u64));
+            group.bench_with_input(
+                BenchmarkId::new(test_case.name(), batch_num),
+                &batch_num,
+                |b, batch_num| {
+                    let jobs = [job(test_case, &pk, *batch_num)];
+                    b.iter(|| api::generate_jobs::<Circuit, _>(&jobs, &config))
+                },
+            );
+        }
+    }
+}
+
+fn bench_prove_mode<SM: SNARKMode>(
+    group: &mut BenchmarkGroup<WallTime>,
+    urs: &UniversalParams<Bls12_377>,
+    mode: &str,
+) {
+    let config = ProverConfig::default();
+    for test_case in TestCase::ALL {
+        let (pk, _) =
+            api::compile_with_encoding::<Circuit, SM>(test_case, Encoding::default(), urs).unwrap();
+        for batch_num in batch_sizes(test_case) {
+            let assignments =
+                api::generate_jobs::<Circuit, _>(&[job(test_case, &pk, batch_num)], &config);
+            group.bench_with_input(
+                BenchmarkId::new(format!("{}/{mode}", test_case.name()), batch_num),
+                &assignments,
+                |b, assignments| {
+                    b.iter(|| api::prove_assignments::<Circuit, _>(urs, assignments, &config))
+                },
+            );
+        }
+    }
+}
+
+fn bench_prove(c: &mut Criterion) {
+    let urs = init();
+    let mut group = slow_group(c, "prove_batch");
+    bench_prove_mode::<VarunaHidingMode>(&mut group, &urs, "hiding");
+    // Same without zero knowledge, to measure its cost
+    bench_prove_mode::<VarunaNonHidingMode>(&mut group, &urs, "non-hiding");
+}
+
+fn bench_verify(c: &mut Criterion) {
+    let urs = init();
+    let config = ProverConfig::default();
+    let fs_parameters = config.fiat_shamir::<Circuit>();
+    let mut group = c.benchmark_group("verify_batch");
+    for test_case in TestCase::ALL {
+        let (pk, vk) = api::compile(test_case, &urs).unwrap();
+        for batch_num in batch_sizes(test_case) {
+            let (proof, inputs) = api::prove(test_case, &urs, &pk, batch_num, &config);
+            let jobs = [(&vk, &inputs[..])];
+            assert!(
+                api::check_jobs::<VarunaHidingMode>(&urs, fs_parameters, &proof, &jobs).unwrap()
+            );
+            group.bench_with_input(
+                BenchmarkId::new(test_case.name(), batch_num),
+                &proof,
+                |b, proof| {
+                    b.iter(|| {
+                        api::check_jobs::<VarunaHidingMode>(&urs, fs_parameters, proof, &jobs)
+                    })
+                },
+            );
+        }
+    }
+}
 
-    // setup
-    let urs = demo::api::setup(1000, 1000, 1000);
+fn bench_end_to_end(c: &mut Criterion) {
+    let urs = init();
+    let mut group = slow_group(c, "proof_and_verify");
 
     // We run 64 times for each batch
     let batch_num = 64;
 
-    for test_case in [TestCase::Test1, TestCase::Test2, TestCase::Test3] {
-        let name = match test_case {
-            TestCase::Test1 => "test1",
-            TestCase::Test2 => "test2",
-            TestCase::Test3 => "test3",
-        };
-        group.bench_function(name, |b| {
+    for test_case in TestCase::ALL {
+        group.bench_function(test_case.name(), |b| {
             b.iter(|| {
-                info!("---------------- round begin ----------------");
-                let circuit_keys = demo::api::compile(test_case, &urs);
+                let circuit_keys = api::compile(test_case, &urs).unwrap();
                 // prove all tuples
                 demo::prove_and_verify(test_case, &urs, &circuit_keys, black_box(batch_num));
-                info!("---------------- round end   ----------------");
             })
         });
     }
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(
+    benches,
+    bench_end_to_end,
+    bench_synthesis,
+    bench_witness,
+    bench_prove,
+    bench_verify
+);
 criterion_main!(benches);
@@ -0,0 +1,39 @@
+//! The builder and the checker return an error on inconsistent files.
+//!
+//! The input is a CBOR array `[r1cs, assignment, lookup]` of the three exported files, so that
+//! the fixtures make a seed corpus, see `corpus/build`. Inputs which do not decode are skipped:
+//! the decoders have their own targets.
+//!
+//! Each input is built as exported and after the optimization passes, which return an error on
+//! unsatisfiable constant constraints, like the builder does.
+
+#![no_main]
+
+use demo::r1cs_provider::builder::{self, BuildOptions};
+use demo::r1cs_provider::checker;
+use demo::r1cs_provider::deserialize::{Assignment, Lookup, R1CS};
+use libfuzzer_sys::fuzz_target;
+use snarkvm_circuit::{Circuit, Environment};
+
+fuzz_target!(|data: &[u8]| {
+    for optimize in [false, true] {
+        let Ok((r1cs, assignment, lookup)) =
+            serde_cbor::from_slice::<(R1CS, Assignment, Option<Lookup>)>(data)
+        else {
+            return;
+        };
+        if !optimize {
+            let _ = checker::check(&r1cs, &assignment, lookup.as_ref());
+        }
+
+        Circuit::reset();
+        let options = BuildOptions {
+            optimize,
+            ..Default::default()
+        };
+        let _ = builder::construct_r1cs_from_parsed::<Circuit>(
+            r1cs, assignment, lookup, None, &options, None,
+        );
+        Circuit::reset();
+    }
+});
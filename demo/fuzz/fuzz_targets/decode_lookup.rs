@@ -0,0 +1,10 @@
+//! The decoder of `lookup.cbor` returns an error on malformed input.
+
+#![no_main]
+
+use demo::r1cs_provider::deserialize::{self, Lookup};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = deserialize::decode::<Lookup>(data, "lookup");
+});
@@ -0,0 +1,10 @@
+//! The decoder of `r1cs.cbor` returns an error on malformed input.
+
+#![no_main]
+
+use demo::r1cs_provider::deserialize::{self, R1CS};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    let _ = deserialize::decode::<R1CS>(data, "r1cs");
+});
@@ -13,26 +13,49 @@
 // limitations under the License.
 
 use anyhow::Context;
-use log::{debug, info};
+use hap_verifier::{Encoding, FsParameters};
+use log::{debug, info, warn};
 use rand::rngs::OsRng;
-use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+#[cfg(any(test, feature = "test-rng"))]
+use rand::SeedableRng;
+#[cfg(any(test, feature = "test-rng"))]
+use rand_chacha::ChaChaRng;
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
 use snarkvm_algorithms::{
     crypto_hash::PoseidonSponge,
     polycommit::kzg10::UniversalParams,
-    snark::varuna::{self, AHPForR1CS, CircuitProvingKey, CircuitVerifyingKey, VarunaHidingMode},
+    snark::varuna::{
+        self, AHPForR1CS, CircuitProvingKey, CircuitVerifyingKey, SNARKMode, VarunaHidingMode,
+    },
     traits::SNARK,
 };
 use snarkvm_circuit::{
     environment::{Assignment, Circuit},
-    Environment as _,
+    Environment,
 };
 use snarkvm_circuit_environment::SameCircuitAssignment;
-use snarkvm_console::{network::Testnet3 as Network, program::Itertools};
-use snarkvm_console_network::Network as _;
+use snarkvm_console::program::Itertools;
+use snarkvm_console_network::Network;
 use snarkvm_curves::bls12_377::{Bls12_377, Fq, Fr};
-use std::{collections::BTreeMap, sync::Arc, time::Instant};
+use std::{
+    collections::BTreeMap,
+    ops::Range,
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+use tracing::{field, info_span, Span};
 
-use crate::{r1cs_provider, TestCase};
+use crate::{
+    input::SignatureInput,
+    params::{self, ParamDir},
+    r1cs_provider::{
+        self,
+        builder::{BuildOptions, CircuitPlan},
+        stats::CircuitStats,
+    },
+    report::Phase,
+    TestCase,
+};
 
 //
 // Aliases
@@ -40,7 +63,10 @@ use crate::{r1cs_provider, TestCase};
 //
 
 type FS = PoseidonSponge<Fq, 2, 1>;
-type VarunaInst = varuna::VarunaSNARK<Bls12_377, FS, VarunaHidingMode>;
+type VarunaInst<SM> = varuna::VarunaSNARK<Bls12_377, FS, SM>;
+
+// The functions generic over a circuit environment `E` build and prove the circuits for the
+// network of `E`, e.g. `Circuit` for Testnet3. The others are shortcuts for Testnet3.
 
 //
 // Functions
@@ -50,46 +76,149 @@ type VarunaInst = varuna::VarunaSNARK<Bls12_377, FS, VarunaHidingMode>;
 /// Our circuit synthesizer for ecdsa.
 ///
 pub fn run_circuit(test_case: TestCase) -> Assignment<Fr> {
+    synthesize(test_case).0
+}
+
+/// Synthesize the circuit, and return its plan along with the assignment.
+pub fn synthesize(test_case: TestCase) -> (Assignment<Fr>, Arc<CircuitPlan>) {
+    synthesize_signature::<Circuit>(test_case, None, Encoding::default())
+}
+
+/// The plan of each circuit, computed by its first synthesis, usually when it is compiled, and
+/// reused by all the following ones. The circuits of the API are always built with the default
+/// options but the encoding.
+static PLANS: Mutex<BTreeMap<(TestCase, Encoding), Arc<CircuitPlan>>> = Mutex::new(BTreeMap::new());
+
+fn cached_plan(test_case: TestCase, encoding: Encoding) -> Option<Arc<CircuitPlan>> {
+    PLANS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .get(&(test_case, encoding))
+        .cloned()
+}
+
+/// Synthesize the circuit verifying `signature`, or a random signature if `None`, with public
+/// inputs laid out as `encoding`.
+pub fn synthesize_signature<E: Environment<BaseField = Fr>>(
+    test_case: TestCase,
+    signature: Option<&SignatureInput>,
+    encoding: Encoding,
+) -> (Assignment<Fr>, Arc<CircuitPlan>) {
     // reset circuit writer
-    Circuit::reset();
+    E::reset();
 
-    r1cs_provider::gnark::build_r1cs(test_case)
+    let options = BuildOptions {
+        encoding,
+        ..Default::default()
+    };
+    let plan = cached_plan(test_case, encoding);
+    let output = r1cs_provider::gnark::build_r1cs::<E>(test_case, signature, &options, plan)
         .context("failed to build circuit")
         .unwrap();
+    PLANS
+        .lock()
+        .unwrap_or_else(|err| err.into_inner())
+        .entry((test_case, encoding))
+        .or_insert_with(|| output.plan.clone());
 
     // return circuit
-    Circuit::eject_assignment_and_reset()
+    let _phase = Phase::Assignment.start();
+    (E::eject_assignment_and_reset(), output.plan)
+}
+
+/// Statistics of the circuit of a test case, see [`CircuitStats`]. The generator only runs if the
+/// circuit was not synthesized yet.
+pub fn circuit_stats(test_case: TestCase) -> CircuitStats {
+    circuit_plan(test_case, None, Encoding::default())
+        .stats
+        .clone()
+}
+
+/// The plan of the circuit of a test case with public inputs laid out as `encoding`, computed by
+/// its first synthesis in this process. If there was none, the circuit is synthesized for
+/// `signature`, or a random signature if `None`.
+pub fn circuit_plan(
+    test_case: TestCase,
+    signature: Option<&SignatureInput>,
+    encoding: Encoding,
+) -> Arc<CircuitPlan> {
+    cached_plan(test_case, encoding)
+        .unwrap_or_else(|| synthesize_signature::<Circuit>(test_case, signature, encoding).1)
+}
+
+/// The maximum degree of the polynomials of a circuit of this size for the hiding mode `SM`,
+/// which determines the SRS blobs loaded by [`setup`], see [`crate::params`]. The hiding mode
+/// needs more powers than the non-hiding one.
+pub fn max_degree<SM: SNARKMode>(
+    num_constraints: usize,
+    num_variables: usize,
+    num_non_zero: usize,
+) -> usize {
+    AHPForR1CS::<Fr, SM>::max_degree(num_constraints, num_variables, num_non_zero).unwrap()
+}
+
+/// Check that the SRS blobs for polynomials of `max_degree` are in `~/.aleo/resources` before
+/// snarkVM loads them, see [`ParamDir::ensure_present`]. Fails with the list of the missing
+/// blobs.
+fn ensure_params(max_degree: usize) -> anyhow::Result<()> {
+    ParamDir::aleo()?.ensure_present(&params::required_blobs(max_degree)?)
 }
 
-/// Setup the parameters.
-pub fn setup(
+/// Setup the parameters for the hiding mode `SM`. Fails if the SRS blobs are not all present,
+/// see [`crate::params`].
+pub fn setup<SM: SNARKMode>(
     num_constraints: usize,
     num_variables: usize,
     num_non_zero: usize,
-) -> UniversalParams<Bls12_377> {
+) -> anyhow::Result<UniversalParams<Bls12_377>> {
     // Note: you can change this to increase the size of the circuit.
     // Of course, the higher these values, the slower the prover...
-    let max_degree = AHPForR1CS::<Fr, VarunaHidingMode>::max_degree(
-        num_constraints,
-        num_variables,
-        num_non_zero,
-    )
-    .unwrap();
-    VarunaInst::universal_setup(max_degree).unwrap()
+    let max_degree = max_degree::<SM>(num_constraints, num_variables, num_non_zero);
+    ensure_params(max_degree)?;
+    Ok(VarunaInst::<SM>::universal_setup(max_degree).unwrap())
 }
 
-/// Compile the circuit.
+/// Compile the circuit, for zero knowledge proofs.
 pub fn compile(
     test_case: TestCase,
     urs: &UniversalParams<Bls12_377>,
-) -> (
+) -> anyhow::Result<(
     CircuitProvingKey<Bls12_377, VarunaHidingMode>,
     CircuitVerifyingKey<Bls12_377>,
-) {
-    info!("compile circuit for {test_case:?}");
+)> {
+    compile_with_encoding::<Circuit, VarunaHidingMode>(test_case, Encoding::default(), urs)
+}
+
+/// Compile the circuit with public inputs laid out as `encoding`, for the hiding mode `SM`:
+/// [`VarunaHidingMode`] for zero knowledge proofs, or `VarunaNonHidingMode` to measure what zero
+/// knowledge costs.
+///
+/// The parameters are extended to the size of the circuit if `urs` is smaller, so this fails
+/// like [`setup`] if the SRS blobs are not all present.
+pub fn compile_with_encoding<E: Environment<BaseField = Fr>, SM: SNARKMode>(
+    test_case: TestCase,
+    encoding: Encoding,
+    urs: &UniversalParams<Bls12_377>,
+) -> anyhow::Result<(
+    CircuitProvingKey<Bls12_377, SM>,
+    CircuitVerifyingKey<Bls12_377>,
+)> {
+    info!(
+        "compile circuit for {test_case:?} ({} encoding)",
+        encoding.name()
+    );
+    let span = info_span!(
+        "compile",
+        test_case = test_case.name(),
+        encoding = encoding.name(),
+        num_constraints = field::Empty
+    );
+    let _span = span.enter();
 
     // Let's get one of the circuits
-    let circuit = run_circuit(test_case);
+    let (circuit, plan) = synthesize_signature::<E>(test_case, None, encoding);
+    let stats = &plan.stats;
+    span.record("num_constraints", stats.num_constraints);
     debug!("circuit: num constraints: {}", circuit.num_constraints());
     debug!(
         "circuit: num lookup tables: {}",
@@ -105,95 +234,534 @@ pub fn compile(
         "circuit: num non-zeros(both non-lookup and lookup): {:?}",
         circuit.num_nonzeros()
     );
+    debug!("circuit: stats: {}", stats.to_json::<SM>()?);
 
-    VarunaInst::batch_circuit_setup(&urs, &[&circuit])
+    ensure_params(stats.max_degree::<SM>()?)?;
+
+    Ok(VarunaInst::<SM>::batch_circuit_setup(&urs, &[&circuit])
         .unwrap()
         .into_iter()
         .next()
-        .expect("there should be one and only one element")
+        .expect("there should be one and only one element"))
 }
 
-/// Run and prove the circuit.
-pub fn prove(
-    test_case: TestCase,
-    urs: &UniversalParams<Bls12_377>,
-    pk: &CircuitProvingKey<Bls12_377, VarunaHidingMode>,
-    batch_num: u32,
-) -> (varuna::Proof<Bls12_377>, Vec<Vec<Fr>>) {
-    let mut pks_to_constraints = BTreeMap::new();
+/// Resources used by [`prove`].
+#[derive(Debug, Clone)]
+pub struct ProverConfig {
+    /// Number of threads for the witness generation and the prover, all cores if `None`.
+    pub num_threads: Option<usize>,
+    /// Maximum number of assignments generated at the same time.
+    pub max_concurrent_witnesses: usize,
+    /// Memory budget in bytes for the assignments. The number of concurrent witness generations
+    /// is reduced so that the estimated size of the assignments stays below it.
+    pub memory_limit: Option<usize>,
+    /// Fiat-Shamir parameters overriding the ones of the network, see
+    /// [`ProverConfig::fiat_shamir`].
+    pub fs_parameters: Option<&'static FsParameters>,
+    /// TEST ONLY: seed of the prover randomness, `OsRng` if `None`.
+    ///
+    /// With a seed and the same signatures, proofs are byte-identical across runs, which is what
+    /// regression fixtures need. The randomness is what makes Varuna zero knowledge, so anyone
+    /// knowing the seed can recover the witness: never set it outside of tests. The setup is
+    /// already deterministic, [`setup`] loads the fixed Aleo SRS.
+    ///
+    /// Only built with the `test-rng` feature, and for the unit tests of the crate.
+    #[cfg(any(test, feature = "test-rng"))]
+    pub test_seed: Option<u64>,
+}
 
-    info!("Generate all circuits (with gnark)");
-    let assignments;
-    let base_assignment = run_circuit(test_case);
+impl Default for ProverConfig {
+    fn default() -> Self {
+        Self {
+            num_threads: None,
+            max_concurrent_witnesses: 5,
+            memory_limit: None,
+            fs_parameters: None,
+            #[cfg(any(test, feature = "test-rng"))]
+            test_seed: None,
+        }
+    }
+}
+
+impl ProverConfig {
+    /// The Fiat-Shamir parameters of proofs for the network of `E`, the verifier must use the
+    /// same.
+    pub fn fiat_shamir<E: Environment>(&self) -> &'static FsParameters {
+        self.fs_parameters
+            .unwrap_or_else(<E::Network as Network>::varuna_fs_parameters)
+    }
+
+    /// Number of assignments to generate concurrently for a batch of `batch_num` instances of a
+    /// circuit described by `stats`.
+    pub fn concurrent_witnesses(&self, stats: &CircuitStats, batch_num: usize) -> usize {
+        let mut concurrency = self
+            .max_concurrent_witnesses
+            .min(self.num_threads.unwrap_or_else(rayon::current_num_threads))
+            .min(batch_num);
+
+        if let Some(memory_limit) = self.memory_limit {
+            let (retained, transient) = estimate_assignment_size(stats);
+            let available = memory_limit.saturating_sub(retained * batch_num);
+            if available < transient {
+                warn!(
+                    "the assignments need about {} bytes, more than the memory limit of {memory_limit} bytes",
+                    retained * batch_num + transient
+                );
+            }
+            concurrency = concurrency.min(available / transient.max(1));
+        }
+
+        concurrency.max(1)
+    }
+}
+
+/// Estimates the memory used by one assignment: what is retained until the proof is computed,
+/// and what is additionally used while it is generated.
+///
+/// The retained part is the values of the variables, the generation also holds the linear
+/// combinations of all the constraints.
+pub fn estimate_assignment_size(stats: &CircuitStats) -> (usize, usize) {
+    let num_variables = stats.num_public + stats.num_private;
+    let num_non_zero = stats.balance.nnz_a + stats.balance.nnz_b + stats.balance.nnz_c;
+
+    let retained = num_variables * std::mem::size_of::<Fr>();
+    let transient =
+        retained + num_non_zero * (std::mem::size_of::<Fr>() + 2 * std::mem::size_of::<usize>());
+    (retained, transient)
+}
 
-    if batch_num == 1 {
-        assignments = Some(vec![SameCircuitAssignment::single_one(base_assignment)]);
-        pks_to_constraints.insert(pk, &assignments.as_ref().unwrap()[..]);
+/// Runs `op` in a thread pool of `num_threads` threads, or in the global pool if `None`.
+fn install<R: Send>(num_threads: Option<usize>, op: impl FnOnce() -> R + Send) -> R {
+    match num_threads {
+        Some(num_threads) => rayon::ThreadPoolBuilder::new()
+            .num_threads(num_threads)
+            .build()
+            .expect("failed to create the thread pool")
+            .install(op),
+        None => op(),
+    }
+}
+
+/// Generate the assignments of the instances of a job.
+fn generate_assignments<E: Environment<BaseField = Fr>, SM: SNARKMode>(
+    job: &ProveJob<SM>,
+    config: &ProverConfig,
+) -> Vec<SameCircuitAssignment<Fr>> {
+    let _span = info_span!(
+        "witness",
+        test_case = job.test_case.name(),
+        encoding = job.encoding.name(),
+        batch_num = job.batch_num
+    )
+    .entered();
+    let signature = |index: u32| job.signatures.map(|signatures| &signatures[index as usize]);
+    let (base_assignment, plan) =
+        synthesize_signature::<E>(job.test_case, signature(0), job.encoding);
+
+    if job.batch_num == 1 {
+        vec![SameCircuitAssignment::single_one(base_assignment)]
     } else {
         let base_assignment = Arc::new(base_assignment);
 
         /* limit num of parallel tasks here for saving memory */
-        let num_parallel_tasks = 5;
-        assignments = Some(
-            (0..batch_num)
-                .into_par_iter()
-                .with_min_len(batch_num as usize / num_parallel_tasks)
-                .map(|_| {
-                    // Note: we use a naive encoding here,
-                    // you can modify it as long as a verifier can still pass tuples `(public key, msg, signature)`.
-                    let assignment = run_circuit(test_case);
-                    SameCircuitAssignment::create_with_base(base_assignment.clone(), assignment)
-                })
-                .collect::<Vec<_>>(),
+        let num_parallel_tasks = config.concurrent_witnesses(&plan.stats, job.batch_num as usize);
+        debug!(
+            "generate {} assignments with {num_parallel_tasks} parallel tasks",
+            job.batch_num
         );
+        /* the instances are synthesized on the rayon threads, in the span of the job */
+        let span = Span::current();
+        synthesize_instances(job.batch_num, num_parallel_tasks, |index| {
+            let _span = span.enter();
+            // Note: we use a naive encoding here,
+            // you can modify it as long as a verifier can still pass tuples `(public key, msg, signature)`.
+            let (assignment, _) =
+                synthesize_signature::<E>(job.test_case, signature(index), job.encoding);
+            let _phase = Phase::Assignment.start();
+            SameCircuitAssignment::create_with_base(base_assignment.clone(), assignment)
+        })
+    }
+}
 
-        pks_to_constraints.insert(pk, &assignments.as_ref().unwrap()[..]);
+/// Runs `synthesize` for the instances `0..batch_num`, at most `num_parallel_tasks` at the same
+/// time.
+///
+/// The bound holds because each of the `num_parallel_tasks` threads of the pool runs one
+/// synthesis at a time: the builder never waits for a parallel iterator on a rayon worker, which
+/// would let the thread start another synthesis, see `builder::try_map`.
+fn synthesize_instances<T: Send>(
+    batch_num: u32,
+    num_parallel_tasks: usize,
+    synthesize: impl Fn(u32) -> T + Sync + Send,
+) -> Vec<T> {
+    install(Some(num_parallel_tasks), || {
+        (0..batch_num)
+            .into_par_iter()
+            .map(synthesize)
+            .collect::<Vec<_>>()
+    })
+}
+
+/// Run and prove the circuit for Testnet3, in the hiding mode of `pk`.
+pub fn prove<SM: SNARKMode>(
+    test_case: TestCase,
+    urs: &UniversalParams<Bls12_377>,
+    pk: &CircuitProvingKey<Bls12_377, SM>,
+    batch_num: u32,
+    config: &ProverConfig,
+) -> (varuna::Proof<Bls12_377>, Vec<Vec<Fr>>) {
+    let job = ProveJob {
+        test_case,
+        pk,
+        batch_num,
+        signatures: None,
+        encoding: Encoding::default(),
+    };
+    let (proof, mut inputs) = prove_jobs::<Circuit, SM>(urs, &[job], config);
+    (proof, inputs.pop().expect("there is one job"))
+}
+
+/// Instances of one circuit to prove with [`prove_jobs`].
+#[derive(Clone, Copy)]
+pub struct ProveJob<'a, SM: SNARKMode = VarunaHidingMode> {
+    pub test_case: TestCase,
+    pub pk: &'a CircuitProvingKey<Bls12_377, SM>,
+    pub batch_num: u32,
+    /// The signature of each instance, `batch_num` of them. Random signatures if `None`.
+    pub signatures: Option<&'a [SignatureInput]>,
+    /// Layout of the public inputs, the proving key must be compiled with the same.
+    pub encoding: Encoding,
+}
+
+/// Run and prove the circuits of several jobs, e.g. a hashing and a signature circuit, or
+/// circuits for different message lengths, in a single batch proof.
+///
+/// Returns the proof and, for each job, the public inputs of its instances. The proof is
+/// verified with one call to [`verify_jobs`]. This is [`generate_jobs`] followed by
+/// [`prove_assignments`].
+pub fn prove_jobs<E: Environment<BaseField = Fr>, SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    jobs: &[ProveJob<SM>],
+    config: &ProverConfig,
+) -> (varuna::Proof<Bls12_377>, Vec<Vec<Vec<Fr>>>) {
+    let _span = info_span!(
+        "prove",
+        num_jobs = jobs.len(),
+        num_instances = jobs.iter().map(|job| job.batch_num).sum::<u32>()
+    )
+    .entered();
+    let assignments = generate_jobs::<E, SM>(jobs, config);
+    let proof = prove_assignments::<E, SM>(urs, &assignments, config);
+    (proof, assignments.inputs())
+}
+
+/// The assignments of the instances of several jobs, see [`generate_jobs`].
+pub struct JobAssignments<'a, SM: SNARKMode = VarunaHidingMode> {
+    /* Jobs with the same proving key are proved as instances of the same circuit */
+    assignments: BTreeMap<&'a CircuitProvingKey<Bls12_377, SM>, Vec<SameCircuitAssignment<Fr>>>,
+    /// The proving key of each job, and the range of its instances in `assignments`.
+    jobs: Vec<(&'a CircuitProvingKey<Bls12_377, SM>, Range<usize>)>,
+}
+
+impl<'a, SM: SNARKMode> JobAssignments<'a, SM> {
+    /// The public inputs of the instances of each job.
+    pub fn inputs(&self) -> Vec<Vec<Vec<Fr>>> {
+        let _phase = Phase::PublicInputs.start();
+        self.jobs
+            .iter()
+            .map(|(pk, range)| {
+                self.assignments[pk][range.clone()]
+                    .iter()
+                    .map(|assignment| {
+                        assignment
+                            .public_inputs()
+                            .iter()
+                            .map(|(_, input)| *input)
+                            .collect_vec()
+                    })
+                    .collect_vec()
+            })
+            .collect_vec()
     }
+}
+
+/// Generate the assignments of the instances of all the jobs, the witness generation part of
+/// [`prove_jobs`].
+pub fn generate_jobs<'a, E: Environment<BaseField = Fr>, SM: SNARKMode>(
+    jobs: &[ProveJob<'a, SM>],
+    config: &ProverConfig,
+) -> JobAssignments<'a, SM> {
+    info!("Generate all circuits (with gnark)");
+
+    let mut assignments = BTreeMap::<_, Vec<_>>::new();
+    let mut ranges = vec![];
+    for job in jobs {
+        if let Some(signatures) = job.signatures {
+            assert_eq!(
+                signatures.len(),
+                job.batch_num as usize,
+                "a job needs one signature per instance"
+            );
+        }
+        let instances = assignments.entry(job.pk).or_default();
+        let start = instances.len();
+        instances.extend(generate_assignments::<E, SM>(job, config));
+        ranges.push((job.pk, start..instances.len()));
+    }
+    JobAssignments {
+        assignments,
+        jobs: ranges,
+    }
+}
+
+/// Prove the assignments of [`generate_jobs`] in a single batch proof, the `prove_batch` part of
+/// [`prove_jobs`].
+pub fn prove_assignments<E: Environment<BaseField = Fr>, SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    assignments: &JobAssignments<SM>,
+    config: &ProverConfig,
+) -> varuna::Proof<Bls12_377> {
+    let pks_to_constraints = assignments
+        .assignments
+        .iter()
+        .map(|(pk, assignments)| (*pk, &assignments[..]))
+        .collect::<BTreeMap<_, _>>();
 
     info!("Compute the proof");
-    let rng = &mut OsRng::default();
-    let universal_prover = urs.to_universal_prover().unwrap();
-    let fiat_shamir = Network::varuna_fs_parameters();
+    let _span = info_span!(
+        "prove_batch",
+        num_circuits = pks_to_constraints.len(),
+        num_instances = pks_to_constraints
+            .values()
+            .map(|assignments| assignments.len())
+            .sum::<usize>()
+    )
+    .entered();
+    let universal_prover = {
+        let _phase = Phase::ProverSetup.start();
+        urs.to_universal_prover().unwrap()
+    };
+    let fiat_shamir = config.fiat_shamir::<E>();
 
     let start = Instant::now();
-    let proof =
-        VarunaInst::prove_batch(&universal_prover, fiat_shamir, &pks_to_constraints, rng).unwrap();
+    let phase = Phase::Prove.start();
+    let proof = install(config.num_threads, || {
+        #[cfg(any(test, feature = "test-rng"))]
+        if let Some(seed) = config.test_seed {
+            warn!("the prover randomness is seeded, the proof is not zero knowledge");
+            let rng = &mut ChaChaRng::seed_from_u64(seed);
+            return VarunaInst::<SM>::prove_batch(
+                &universal_prover,
+                fiat_shamir,
+                &pks_to_constraints,
+                rng,
+            );
+        }
+        let rng = &mut OsRng::default();
+        VarunaInst::<SM>::prove_batch(&universal_prover, fiat_shamir, &pks_to_constraints, rng)
+    })
+    .unwrap();
+    drop(phase);
     let duration = start.elapsed();
     info!("Compute the proof finished ({duration:?})");
-
-    /* Prepare inputs for verifier, this should be verify fast since it is just memory copy ... */
-    info!("Prepare inputs for verifier");
-
-    let inputs = assignments
-        .as_ref()
-        .unwrap()
-        .iter()
-        .map(|assignment| {
-            assignment
-                .public_inputs()
-                .iter()
-                .map(|(_, input)| *input)
-                .collect_vec()
-        })
-        .collect_vec();
-
-    (proof, inputs)
+    proof
 }
 
-/// Verify a proof.
-pub fn verify_proof(
+/// Verify a proof of the hiding mode `SM`, with the Fiat-Shamir parameters of the prover, see
+/// [`ProverConfig::fiat_shamir`].
+pub fn verify_proof<SM: SNARKMode>(
     urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
     proof: &varuna::Proof<Bls12_377>,
     vks_to_inputs: &BTreeMap<&CircuitVerifyingKey<Bls12_377>, &[Vec<Fr>]>,
 ) {
-    // verify
-    let fiat_shamir = Network::varuna_fs_parameters();
-    let universal_verifier = urs.to_universal_verifier().unwrap();
+    assert!(
+        check_proof::<SM>(urs, fs_parameters, proof, vks_to_inputs).unwrap(),
+        "the proof is invalid"
+    );
+}
 
+/// Verify a proof, and return whether it is valid.
+pub fn check_proof<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    proof: &varuna::Proof<Bls12_377>,
+    vks_to_inputs: &BTreeMap<&CircuitVerifyingKey<Bls12_377>, &[Vec<Fr>]>,
+) -> anyhow::Result<bool> {
     // Note: same comment here, verify_batch could verify several proofs instead of one ;)
     info!("Verify the proof");
+    let _span = info_span!(
+        "verify",
+        num_circuits = vks_to_inputs.len(),
+        num_instances = vks_to_inputs
+            .values()
+            .map(|inputs| inputs.len())
+            .sum::<usize>()
+    )
+    .entered();
+    let start = Instant::now();
+    let _phase = Phase::Verify.start();
+    let valid = hap_verifier::verify_batch::<SM>(urs, fs_parameters, proof, vks_to_inputs)?;
+    let duration = start.elapsed();
+    info!("Verify the proof finished ({duration:?})");
+    Ok(valid)
+}
+
+/// Verify a proof produced by [`prove_jobs`], given the verifying key and the public inputs of
+/// each job, in the order of the jobs.
+pub fn verify_jobs<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    proof: &varuna::Proof<Bls12_377>,
+    jobs: &[(&CircuitVerifyingKey<Bls12_377>, &[Vec<Fr>])],
+) {
+    assert!(
+        check_jobs::<SM>(urs, fs_parameters, proof, jobs).unwrap(),
+        "the proof is invalid"
+    );
+}
+
+/// Like [`verify_jobs`], but return whether the proof is valid.
+pub fn check_jobs<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    proof: &varuna::Proof<Bls12_377>,
+    jobs: &[(&CircuitVerifyingKey<Bls12_377>, &[Vec<Fr>])],
+) -> anyhow::Result<bool> {
+    info!("Verify the proof");
+    let _span = info_span!(
+        "verify",
+        num_circuits = jobs.len(),
+        num_instances = jobs.iter().map(|(_, inputs)| inputs.len()).sum::<usize>()
+    )
+    .entered();
     let start = Instant::now();
-    VarunaInst::verify_batch(&universal_verifier, fiat_shamir, vks_to_inputs, proof).unwrap();
+    let _phase = Phase::Verify.start();
+    let valid = hap_verifier::verify_jobs::<SM>(urs, fs_parameters, proof, jobs)?;
     let duration = start.elapsed();
     info!("Verify the proof finished ({duration:?})");
+    Ok(valid)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use snarkvm_circuit_environment::prelude::snarkvm_fields::One as _;
+    use snarkvm_utilities::ToBytes;
+
+    use super::*;
+    use crate::r1cs_provider::{builder, fixtures};
+
+    /// The fixture circuit with its satisfying assignment, without gnark-circuit-gen.
+    fn fixture_assignment() -> Assignment<Fr> {
+        Circuit::reset();
+        builder::construct_r1cs_from_file::<Circuit>(
+            fixtures::path("r1cs"),
+            fixtures::path(fixtures::SATISFIED),
+            Some(fixtures::path("lookup")),
+            None,
+            &BuildOptions::default(),
+            None,
+        )
+        .unwrap();
+        Circuit::eject_assignment_and_reset()
+    }
+
+    #[test]
+    fn test_synthesis_concurrency() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        let num_constraints = synthesize_instances(16, 2, |_| {
+            let running_now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(running_now, Ordering::SeqCst);
+            let circuit = fixture_assignment();
+            running.fetch_sub(1, Ordering::SeqCst);
+            circuit.num_constraints()
+        });
+        assert_eq!(num_constraints, vec![3; 16]);
+        let peak = peak.load(Ordering::SeqCst);
+        assert!(peak <= 2, "{peak} syntheses ran at the same time");
+    }
+
+    /// The universal parameters and the keys of the fixture circuit.
+    fn fixture_keys() -> (
+        UniversalParams<Bls12_377>,
+        CircuitProvingKey<Bls12_377, VarunaHidingMode>,
+        CircuitVerifyingKey<Bls12_377>,
+    ) {
+        let urs = setup::<VarunaHidingMode>(100, 100, 100).unwrap();
+        let (pk, vk) =
+            VarunaInst::<VarunaHidingMode>::batch_circuit_setup(&urs, &[&fixture_assignment()])
+                .unwrap()
+                .into_iter()
+                .next()
+                .unwrap();
+        (urs, pk, vk)
+    }
+
+    /// Two instances of the fixture circuit.
+    fn fixture_jobs(pk: &CircuitProvingKey<Bls12_377, VarunaHidingMode>) -> JobAssignments<'_> {
+        JobAssignments {
+            assignments: BTreeMap::from([(
+                pk,
+                vec![
+                    SameCircuitAssignment::single_one(fixture_assignment()),
+                    SameCircuitAssignment::single_one(fixture_assignment()),
+                ],
+            )]),
+            jobs: vec![(pk, 0..2)],
+        }
+    }
+
+    #[test]
+    fn test_prove_fixture() -> anyhow::Result<()> {
+        let (urs, pk, vk) = fixture_keys();
+        let assignments = fixture_jobs(&pk);
+        let config = ProverConfig {
+            test_seed: Some(0),
+            ..Default::default()
+        };
+        let proof = prove_assignments::<Circuit, VarunaHidingMode>(&urs, &assignments, &config);
+        let fs_parameters = config.fiat_shamir::<Circuit>();
+
+        let inputs = assignments.inputs().pop().unwrap();
+        assert_eq!(inputs.len(), 2);
+        let jobs = [(&vk, &inputs[..])];
+        assert!(check_jobs::<VarunaHidingMode>(
+            &urs,
+            fs_parameters,
+            &proof,
+            &jobs
+        )?);
+
+        let mut tampered = inputs.clone();
+        *tampered[1].last_mut().unwrap() += Fr::one();
+        let jobs = [(&vk, &tampered[..])];
+        assert!(
+            !check_jobs::<VarunaHidingMode>(&urs, fs_parameters, &proof, &jobs).unwrap_or(false)
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_seeded_proofs() -> anyhow::Result<()> {
+        let (urs, pk, vk) = fixture_keys();
+        /* the setup is deterministic, the keys are the same without a seed */
+        let (_, _, other_vk) = fixture_keys();
+        assert_eq!(vk.to_bytes_le()?, other_vk.to_bytes_le()?);
+
+        let prove = |seed| {
+            let config = ProverConfig {
+                test_seed: Some(seed),
+                ..Default::default()
+            };
+            let assignments = fixture_jobs(&pk);
+            prove_assignments::<Circuit, VarunaHidingMode>(&urs, &assignments, &config)
+                .to_bytes_le()
+        };
+        assert_eq!(prove(7)?, prove(7)?);
+        assert_ne!(prove(7)?, prove(8)?);
+        Ok(())
+    }
 }
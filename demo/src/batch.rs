@@ -0,0 +1,169 @@
+//! Planning of large batches over several Varuna proofs.
+//!
+//! A single `prove_batch` over a huge batch keeps every assignment in memory until the proof is
+//! computed. The planner splits the signatures into chunks of bounded size, one proof per
+//! chunk, and keeps track of which proof and instance verifies each signature. The chunks are
+//! proved one after another, so that only the assignments of one chunk are in memory.
+
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+use hap_verifier::{Encoding, FsParameters};
+use log::info;
+use snarkvm_algorithms::{
+    polycommit::kzg10::UniversalParams,
+    snark::varuna::{self, CircuitProvingKey, CircuitVerifyingKey, SNARKMode},
+};
+use snarkvm_circuit::Circuit;
+use snarkvm_curves::bls12_377::{Bls12_377, Fr};
+
+use crate::api::{self, ProveJob, ProverConfig};
+use crate::input::SignatureInput;
+use crate::r1cs_provider::stats::CircuitStats;
+use crate::TestCase;
+
+/// How the signatures are split into proofs.
+#[derive(Debug, Clone, Default)]
+pub struct PlannerConfig {
+    /// Maximum number of signatures per proof. If `None`, it is derived from the memory limit
+    /// of the [`ProverConfig`], and all signatures go into one proof without a limit.
+    pub max_batch_size: Option<usize>,
+}
+
+impl PlannerConfig {
+    /// The maximum number of signatures per proof for a circuit described by `stats`, such that
+    /// the assignments of one proof fit in the memory limit.
+    pub fn max_batch_size(&self, stats: &CircuitStats, prover: &ProverConfig) -> Option<usize> {
+        self.max_batch_size.or_else(|| {
+            let memory_limit = prover.memory_limit?;
+            let (retained, transient) = api::estimate_assignment_size(stats);
+            let concurrent = prover.max_concurrent_witnesses.max(1);
+            let available = memory_limit.saturating_sub(transient * concurrent);
+            Some((available / retained.max(1)).max(1))
+        })
+    }
+}
+
+/// The signatures verified by each proof, as ranges of signature indices.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchPlan {
+    pub chunks: Vec<Range<usize>>,
+}
+
+impl BatchPlan {
+    /// Splits `num_signatures` signatures into the least number of chunks of at most
+    /// `max_batch_size` signatures, with sizes differing by at most one.
+    pub fn new(num_signatures: usize, max_batch_size: Option<usize>) -> Self {
+        let max_batch_size = max_batch_size.unwrap_or(num_signatures).max(1);
+        let num_chunks = num_signatures.div_ceil(max_batch_size);
+
+        let mut chunks = Vec::with_capacity(num_chunks);
+        let mut start = 0;
+        for i in 0..num_chunks {
+            let len = num_signatures / num_chunks + usize::from(i < num_signatures % num_chunks);
+            chunks.push(start..start + len);
+            start += len;
+        }
+        Self { chunks }
+    }
+
+    /// The proof and the instance in this proof which verify `signature`.
+    pub fn locate(&self, signature: usize) -> Option<(usize, usize)> {
+        let proof = self.chunks.partition_point(|chunk| chunk.end <= signature);
+        let chunk = self.chunks.get(proof)?;
+        chunk
+            .contains(&signature)
+            .then_some((proof, signature.wrapping_sub(chunk.start)))
+    }
+}
+
+/// The proofs of a planned batch, with the public inputs of their instances.
+pub struct BatchProofs {
+    pub plan: BatchPlan,
+    pub proofs: Vec<(varuna::Proof<Bls12_377>, Vec<Vec<Fr>>)>,
+}
+
+impl BatchProofs {
+    /// The public inputs of the instance which verifies `signature`.
+    pub fn inputs(&self, signature: usize) -> Option<&[Fr]> {
+        let (proof, instance) = self.plan.locate(signature)?;
+        Some(&self.proofs[proof].1[instance])
+    }
+}
+
+/// Proves `signatures` with the circuit of `test_case`, compiled with `encoding` into `pk`, with
+/// as many proofs as required by `planner`. The proofs are computed one after another.
+pub fn prove<SM: SNARKMode>(
+    test_case: TestCase,
+    urs: &UniversalParams<Bls12_377>,
+    pk: &CircuitProvingKey<Bls12_377, SM>,
+    signatures: &[SignatureInput],
+    encoding: Encoding,
+    planner: &PlannerConfig,
+    prover: &ProverConfig,
+) -> BatchProofs {
+    /* the statistics of the first synthesis of the circuit, usually the one which compiled `pk` */
+    let circuit = api::circuit_plan(test_case, signatures.first(), encoding);
+    let plan = BatchPlan::new(
+        signatures.len(),
+        planner.max_batch_size(&circuit.stats, prover),
+    );
+    info!(
+        "prove {} signatures with {} proofs",
+        signatures.len(),
+        plan.chunks.len()
+    );
+
+    let proofs = plan
+        .chunks
+        .iter()
+        .map(|chunk| {
+            let job = ProveJob {
+                test_case,
+                pk,
+                batch_num: chunk.len() as u32,
+                signatures: Some(&signatures[chunk.clone()]),
+                encoding,
+            };
+            let (proof, mut inputs) = api::prove_jobs::<Circuit, SM>(urs, &[job], prover);
+            (proof, inputs.pop().expect("there is one job"))
+        })
+        .collect();
+
+    BatchProofs { plan, proofs }
+}
+
+/// Verifies all the proofs of a planned batch, proved in the hiding mode `SM` with
+/// `fs_parameters`, and returns whether they are all valid.
+pub fn verify<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    vk: &CircuitVerifyingKey<Bls12_377>,
+    proofs: &BatchProofs,
+) -> anyhow::Result<bool> {
+    for (proof, inputs) in &proofs.proofs {
+        let vks_to_inputs = BTreeMap::from([(vk, &inputs[..])]);
+        if !api::check_proof::<SM>(urs, fs_parameters, proof, &vks_to_inputs)? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        let plan = BatchPlan::new(10, Some(4));
+        assert_eq!(plan.chunks, vec![0..4, 4..7, 7..10]);
+        assert_eq!(plan.locate(0), Some((0, 0)));
+        assert_eq!(plan.locate(5), Some((1, 1)));
+        assert_eq!(plan.locate(9), Some((2, 2)));
+        assert_eq!(plan.locate(10), None);
+
+        assert_eq!(BatchPlan::new(10, None).chunks, vec![0..10]);
+        assert_eq!(BatchPlan::new(0, Some(4)).chunks, vec![]);
+    }
+}
@@ -0,0 +1,521 @@
+//! Command line interface to the proving pipeline.
+//!
+//! ```text
+//! hap setup   --out urs.bin [--test-case test1 ...]
+//! hap compile --urs urs.bin --keys keys/ --test-case test1 [--encoding packed]
+//! hap prove   --urs urs.bin --keys keys/ (--test-case test1 [--batch 64] | --signatures sigs.json) --out proof.json
+//! hap verify  --urs urs.bin --keys keys/ --bundle proof.json [--signatures sigs.json]
+//! hap inspect --r1cs r1cs.cbor --assignment assignment.cbor [--lookup lookup.cbor] [--labels labels.cbor]
+//! hap serve   --urs urs.bin --keys keys/ --queue jobs/ [--addr 127.0.0.1:8080]
+//! hap check   --r1cs r1cs.cbor --assignment assignment.cbor [--lookup lookup.cbor] [--labels labels.cbor]
+//! hap params  (list [--test-case test1 ...] | import <dir or archive> | verify [--test-case test1 ...])
+//! ```
+//!
+//! With `--trace <file>`, the spans of the pipeline stages are written to the file, see
+//! [`demo::trace`].
+//!
+//! With `--non-hiding`, `compile`, `prove`, `verify` and `serve` use the non-hiding mode of Varuna,
+//! whose proofs are not zero knowledge. The keys and proofs of one mode cannot be used with the
+//! other, so all the commands of a pipeline need the same flag. `setup`, `inspect` and `params`
+//! size the parameters for the mode, which needs fewer powers without hiding.
+//!
+//! The keys of a test case are stored as `<keys>/<circuit>.pk` and `<keys>/<circuit>.vk`, where the
+//! circuit is named after the test case and the encoding of its public inputs, e.g. `test1` or
+//! `test1-packed`.
+
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Result};
+use clap::{Parser, Subcommand};
+use hap_verifier::{Encoding, SignatureCircuit, VarunaHidingMode, VarunaNonHidingMode};
+use log::{info, LevelFilter};
+use snarkvm_algorithms::{
+    polycommit::kzg10::UniversalParams,
+    snark::varuna::{CircuitProvingKey, CircuitVerifyingKey, SNARKMode},
+};
+use snarkvm_circuit::Circuit;
+use snarkvm_curves::bls12_377::Bls12_377;
+
+use demo::api::{self, ProveJob, ProverConfig};
+use demo::bucket::{self, BucketRegistry, KeyCache};
+use demo::bundle::{self, ProofBundle};
+use demo::input;
+use demo::params::{self, BlobStatus, ParamDir};
+use demo::r1cs_provider::balance::BalanceStrategy;
+use demo::r1cs_provider::{checker, deserialize, optimize, stats::CircuitStats};
+use demo::server::{self, JobQueue, KeyProver};
+use demo::TestCase;
+
+#[derive(Parser)]
+#[clap(
+    name = "hap",
+    about = "Prove and verify batches of ECDSA signatures with Varuna"
+)]
+struct Cli {
+    /// Write the spans of the pipeline stages to this file, as JSON lines.
+    #[clap(long, global = true)]
+    trace: Option<PathBuf>,
+    /// Use the non-hiding mode of Varuna, whose proofs are not zero knowledge.
+    #[clap(long, global = true)]
+    non_hiding: bool,
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the universal setup, large enough for the given test cases.
+    Setup {
+        #[clap(long = "test-case", value_parser = TestCase::from_str)]
+        test_cases: Vec<TestCase>,
+        #[clap(long)]
+        out: PathBuf,
+    },
+    /// Compile the circuit of a test case into proving and verifying keys.
+    Compile {
+        #[clap(long)]
+        urs: PathBuf,
+        #[clap(long)]
+        keys: PathBuf,
+        #[clap(long, value_parser = TestCase::from_str)]
+        test_case: TestCase,
+        /// Layout of the public inputs, `bytes` or `packed`. gnark-circuit-gen only
+        /// exports `bytes` circuits, which are repacked for `packed`.
+        #[clap(long, value_parser = Encoding::from_str, default_value = "bytes")]
+        encoding: Encoding,
+    },
+    /// Prove a batch of signatures and write a proof bundle.
+    Prove {
+        #[clap(long)]
+        urs: PathBuf,
+        #[clap(long)]
+        keys: PathBuf,
+        /// Prove `batch` instances of the circuit of this test case.
+        #[clap(long, value_parser = TestCase::from_str, conflicts_with = "signatures")]
+        test_case: Option<TestCase>,
+        #[clap(long, default_value = "1")]
+        batch: u32,
+        /// Prove the signatures of a JSON or CSV file, grouped by message length.
+        #[clap(long)]
+        signatures: Option<PathBuf>,
+        #[clap(long, value_parser = Encoding::from_str, default_value = "bytes")]
+        encoding: Encoding,
+        #[clap(long)]
+        out: PathBuf,
+    },
+    /// Verify a proof bundle.
+    Verify {
+        #[clap(long)]
+        urs: PathBuf,
+        #[clap(long)]
+        keys: PathBuf,
+        #[clap(long)]
+        bundle: PathBuf,
+        /// Compute the public inputs from these signatures instead of using the ones of the
+        /// bundle.
+        #[clap(long)]
+        signatures: Option<PathBuf>,
+        /// Layout of the public inputs computed from `signatures`.
+        #[clap(long, value_parser = Encoding::from_str, default_value = "bytes")]
+        encoding: Encoding,
+    },
+    /// Serve a proving service over HTTP, see `demo::server`.
+    Serve {
+        #[clap(long)]
+        urs: PathBuf,
+        /// Keys of all test cases found in this directory are loaded at startup.
+        #[clap(long)]
+        keys: PathBuf,
+        /// Directory of the persistent job queue.
+        #[clap(long)]
+        queue: PathBuf,
+        /// Layout of the public inputs of the signature jobs.
+        #[clap(long, value_parser = Encoding::from_str, default_value = "bytes")]
+        encoding: Encoding,
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+    /// Print the statistics of an exported circuit as JSON.
+    Inspect {
+        #[clap(flatten)]
+        circuit: CircuitFiles,
+        #[clap(long, value_parser = parse_balance, default_value = "greedy")]
+        balance: BalanceStrategy,
+        /// Run the optimization passes before computing the statistics. This is only an
+        /// inspection: `compile` and `prove` always build the circuit as exported.
+        #[clap(long)]
+        optimize: bool,
+    },
+    /// Check that an exported assignment satisfies its circuit.
+    Check {
+        #[clap(flatten)]
+        circuit: CircuitFiles,
+    },
+    /// Manage the SRS blobs of the universal setup in `~/.aleo/resources`, see `demo::params`.
+    Params {
+        #[clap(subcommand)]
+        command: ParamsCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum ParamsCommand {
+    /// List the blobs needed by the setup of the given test cases, and whether they are present.
+    List {
+        #[clap(long = "test-case", value_parser = TestCase::from_str)]
+        test_cases: Vec<TestCase>,
+    },
+    /// Import the blobs of a directory or a `.tar`, `.tar.gz` or `.tgz` archive.
+    Import { source: PathBuf },
+    /// Check the blobs needed by the setup of the given test cases against their checksums.
+    Verify {
+        #[clap(long = "test-case", value_parser = TestCase::from_str)]
+        test_cases: Vec<TestCase>,
+    },
+}
+
+#[derive(clap::Args)]
+struct CircuitFiles {
+    #[clap(long)]
+    r1cs: PathBuf,
+    #[clap(long)]
+    assignment: PathBuf,
+    #[clap(long)]
+    lookup: Option<PathBuf>,
+    #[clap(long)]
+    labels: Option<PathBuf>,
+}
+
+fn parse_balance(s: &str) -> Result<BalanceStrategy> {
+    Ok(match s {
+        "none" => BalanceStrategy::None,
+        "greedy" => BalanceStrategy::Greedy,
+        "partition" => BalanceStrategy::Partition,
+        "varuna-cost" => BalanceStrategy::VarunaCost,
+        _ => bail!("unknown strategy {s:?}, expected none, greedy, partition or varuna-cost"),
+    })
+}
+
+fn main() -> Result<()> {
+    env_logger::builder()
+        .filter_level(LevelFilter::Info)
+        .parse_default_env()
+        .init();
+
+    let cli = Cli::parse();
+    if let Some(trace) = &cli.trace {
+        demo::trace::init_file_collector(trace)?;
+    }
+    if cli.non_hiding {
+        run::<VarunaNonHidingMode>(cli.command)
+    } else {
+        run::<VarunaHidingMode>(cli.command)
+    }
+}
+
+/// Runs a command, with the keys and proofs of the hiding mode `SM`.
+fn run<SM: SNARKMode>(command: Command) -> Result<()> {
+    match command {
+        Command::Setup { test_cases, out } => setup::<SM>(&test_cases, &out),
+        Command::Compile {
+            urs,
+            keys,
+            test_case,
+            encoding,
+        } => {
+            let urs = read_urs(&urs)?;
+            let (pk, vk) = api::compile_with_encoding::<Circuit, SM>(test_case, encoding, &urs)?;
+            std::fs::create_dir_all(&keys).with_context(|| format!("failed to create {keys:?}"))?;
+            bundle::write_bytes(key_path(&keys, test_case, encoding, "pk"), &pk)?;
+            bundle::write_bytes(key_path(&keys, test_case, encoding, "vk"), &vk)
+        }
+        Command::Prove {
+            urs,
+            keys,
+            test_case,
+            batch,
+            signatures,
+            encoding,
+            out,
+        } => {
+            let urs = read_urs(&urs)?;
+            let bundle = match (test_case, signatures) {
+                (Some(test_case), None) => {
+                    let pk = read_pk::<SM>(&keys, test_case, encoding)?;
+                    let job = ProveJob {
+                        test_case,
+                        pk: &pk,
+                        batch_num: batch,
+                        signatures: None,
+                        encoding,
+                    };
+                    let (proof, inputs) =
+                        api::prove_jobs::<Circuit, _>(&urs, &[job], &ProverConfig::default());
+                    let name = test_case.circuit_name(encoding);
+                    ProofBundle::new(&proof, &[(&name, &inputs[0])])?
+                }
+                (None, Some(signatures)) => {
+                    prove_signatures::<SM>(&urs, &keys, &signatures, encoding)?
+                }
+                _ => bail!("either --test-case or --signatures is required"),
+            };
+            bundle.write(out)
+        }
+        Command::Verify {
+            urs,
+            keys,
+            bundle,
+            signatures,
+            encoding,
+        } => {
+            let urs = read_urs(&urs)?;
+            let bundle = ProofBundle::read(bundle)?;
+            let valid = match signatures {
+                Some(signatures) => {
+                    let signatures = input::read_signatures(signatures)?;
+                    let msgs = signatures
+                        .iter()
+                        .map(|signature| &signature.message)
+                        .collect::<Vec<_>>();
+                    let circuits = BucketRegistry::default()
+                        .with_encoding(encoding)
+                        .group(&msgs)?
+                        .into_iter()
+                        .map(|(bucket, _)| -> Result<_> {
+                            Ok(SignatureCircuit {
+                                max_len: bucket.max_len,
+                                encoding,
+                                vk: read_vk(&keys, bucket.test_case, encoding)?,
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?;
+                    hap_verifier::verify_signatures::<SM>(
+                        &urs,
+                        hap_verifier::fs_parameters(),
+                        &circuits,
+                        &bundle.proof()?,
+                        &signatures,
+                    )?
+                }
+                None => {
+                    let mut vks = BTreeMap::new();
+                    for job in &bundle.jobs {
+                        let (test_case, encoding) = TestCase::parse_circuit_name(&job.circuit)?;
+                        vks.insert(job.circuit.clone(), read_vk(&keys, test_case, encoding)?);
+                    }
+                    hap_verifier::verify_bundle::<SM>(
+                        &urs,
+                        hap_verifier::fs_parameters(),
+                        &vks,
+                        &bundle,
+                    )?
+                }
+            };
+            if !valid {
+                bail!("the proof is invalid");
+            }
+            println!("the proof is valid");
+            Ok(())
+        }
+        Command::Serve {
+            urs,
+            keys,
+            queue,
+            encoding,
+            addr,
+        } => {
+            let urs = read_urs(&urs)?;
+            let cache = KeyCache::<SM>::new(&urs);
+            for test_case in TestCase::ALL {
+                for encoding in Encoding::ALL {
+                    if key_path(&keys, test_case, encoding, "pk").exists() {
+                        info!("load keys of {}", test_case.circuit_name(encoding));
+                        let pk = read_pk(&keys, test_case, encoding)?;
+                        let vk = read_vk(&keys, test_case, encoding)?;
+                        cache.insert(test_case, encoding, (pk, vk));
+                    }
+                }
+            }
+            let prover = KeyProver {
+                registry: BucketRegistry::default().with_encoding(encoding),
+                keys: cache,
+                config: ProverConfig::default(),
+            };
+            let queue = JobQueue::open(&queue)?;
+            let listener =
+                TcpListener::bind(&addr).with_context(|| format!("failed to bind {addr}"))?;
+            server::serve(listener, &queue, &prover)
+        }
+        Command::Inspect {
+            circuit,
+            balance,
+            optimize,
+        } => {
+            let (r1cs, assignment, lookup, labels) = read_circuit(&circuit)?;
+            let (r1cs, lookup, labels) = if optimize {
+                let optimized =
+                    optimize::optimize(&r1cs, lookup.as_ref(), assignment.num_public_inputs)?;
+                info!("optimize: {:?}", optimized.report);
+                let labels = labels.map(|labels| {
+                    labels.select(&optimized.kept_constraints, &optimized.kept_lookups)
+                });
+                (optimized.r1cs, optimized.lookup, labels)
+            } else {
+                (r1cs, lookup, labels)
+            };
+            let stats = CircuitStats::new(
+                &r1cs,
+                &assignment,
+                lookup.as_ref(),
+                labels.as_ref(),
+                balance,
+            )?;
+            println!("{}", stats.to_json::<SM>()?);
+            Ok(())
+        }
+        Command::Check { circuit } => {
+            let (r1cs, assignment, lookup, labels) = read_circuit(&circuit)?;
+            checker::ensure_satisfied(&r1cs, &assignment, lookup.as_ref(), labels.as_ref())?;
+            println!("the assignment satisfies the circuit");
+            Ok(())
+        }
+        Command::Params { command } => run_params::<SM>(command),
+    }
+}
+
+fn setup<SM: SNARKMode>(test_cases: &[TestCase], out: &Path) -> Result<()> {
+    let (num_constraints, num_variables, num_non_zero) = setup_size(test_cases);
+    /* checks the SRS blobs first, see `demo::params` */
+    let urs = api::setup::<SM>(num_constraints, num_variables, num_non_zero)?;
+    bundle::write_bytes(out, &urs)
+}
+
+/// The number of constraints, variables and non-zero entries the setup is sized for.
+fn setup_size(test_cases: &[TestCase]) -> (usize, usize, usize) {
+    // Same default size as the benchmarks, the parameters are extended when compiling.
+    let (mut num_constraints, mut num_variables, mut num_non_zero) = (1000, 1000, 1000);
+    for test_case in test_cases {
+        let stats = api::circuit_stats(*test_case);
+        num_constraints = num_constraints.max(stats.num_constraints + stats.num_lookup_constraints);
+        num_variables = num_variables.max(stats.num_public + stats.num_private);
+        num_non_zero = num_non_zero
+            .max(stats.balance.nnz_a)
+            .max(stats.balance.nnz_b)
+            .max(stats.balance.nnz_c);
+    }
+    (num_constraints, num_variables, num_non_zero)
+}
+
+/// Runs a `params` command, for the SRS blobs of the hiding mode `SM`.
+fn run_params<SM: SNARKMode>(command: ParamsCommand) -> Result<()> {
+    let dir = ParamDir::aleo()?;
+    match command {
+        ParamsCommand::List { test_cases } => {
+            let (num_constraints, num_variables, num_non_zero) = setup_size(&test_cases);
+            let max_degree = api::max_degree::<SM>(num_constraints, num_variables, num_non_zero);
+            println!(
+                "maximum degree {max_degree}, powers up to 2^{}",
+                params::required_degree(max_degree)
+            );
+            for blob in params::required_blobs(max_degree)? {
+                let status = match dir.status(&blob)? {
+                    BlobStatus::Present => "ok".to_string(),
+                    BlobStatus::Missing => "missing".to_string(),
+                    BlobStatus::Corrupted(reason) => format!("corrupted: {reason}"),
+                };
+                println!("{} ({} bytes): {status}", blob.file_name(), blob.size());
+            }
+            Ok(())
+        }
+        ParamsCommand::Import { source } => {
+            let blobs = dir.import(&source)?;
+            if blobs.is_empty() {
+                bail!("no SRS blob found in {source:?}");
+            }
+            for blob in blobs {
+                println!("imported {}", dir.path(&blob).display());
+            }
+            Ok(())
+        }
+        ParamsCommand::Verify { test_cases } => {
+            let (num_constraints, num_variables, num_non_zero) = setup_size(&test_cases);
+            let max_degree = api::max_degree::<SM>(num_constraints, num_variables, num_non_zero);
+            dir.ensure(&params::required_blobs(max_degree)?)?;
+            println!("all the SRS blobs are present in {}", dir.dir().display());
+            Ok(())
+        }
+    }
+}
+
+fn prove_signatures<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    keys: &Path,
+    signatures: &Path,
+    encoding: Encoding,
+) -> Result<ProofBundle> {
+    let signatures = input::read_signatures(signatures)?;
+    let msgs = signatures
+        .iter()
+        .map(|signature| &signature.message)
+        .collect::<Vec<_>>();
+
+    let registry = BucketRegistry::default().with_encoding(encoding);
+    let cache = KeyCache::<SM>::new(urs);
+    for (bucket, _) in registry.group(&msgs)? {
+        let pk = read_pk(keys, bucket.test_case, encoding)?;
+        let vk = read_vk(keys, bucket.test_case, encoding)?;
+        cache.insert(bucket.test_case, encoding, (pk, vk));
+    }
+
+    bucket::prove(&registry, &cache, &signatures, &ProverConfig::default())?.bundle()
+}
+
+type ExportedCircuit = (
+    deserialize::R1CS,
+    deserialize::Assignment,
+    Option<deserialize::Lookup>,
+    Option<deserialize::Labels>,
+);
+
+fn read_circuit(files: &CircuitFiles) -> Result<ExportedCircuit> {
+    let (r1cs, assignment, lookup) =
+        deserialize::parse_file(&files.r1cs, &files.assignment, files.lookup.as_ref())?;
+    let labels = files
+        .labels
+        .as_ref()
+        .map(deserialize::parse_labels)
+        .transpose()?;
+    Ok((r1cs, assignment, lookup, labels))
+}
+
+fn read_urs(path: &Path) -> Result<UniversalParams<Bls12_377>> {
+    bundle::read_bytes(path)
+}
+
+/// `<keys>/<circuit name>.<extension>`, see [`TestCase::circuit_name`].
+fn key_path(keys: &Path, test_case: TestCase, encoding: Encoding, extension: &str) -> PathBuf {
+    keys.join(format!("{}.{extension}", test_case.circuit_name(encoding)))
+}
+
+fn read_pk<SM: SNARKMode>(
+    keys: &Path,
+    test_case: TestCase,
+    encoding: Encoding,
+) -> Result<CircuitProvingKey<Bls12_377, SM>> {
+    let name = test_case.circuit_name(encoding);
+    bundle::read_bytes(key_path(keys, test_case, encoding, "pk"))
+        .with_context(|| format!("missing proving key for {name}, run `hap compile` first"))
+}
+
+fn read_vk(
+    keys: &Path,
+    test_case: TestCase,
+    encoding: Encoding,
+) -> Result<CircuitVerifyingKey<Bls12_377>> {
+    let name = test_case.circuit_name(encoding);
+    bundle::read_bytes(key_path(keys, test_case, encoding, "vk"))
+        .with_context(|| format!("missing verifying key for {name}, run `hap compile` first"))
+}
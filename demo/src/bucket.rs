@@ -0,0 +1,381 @@
+//! Message length buckets, with one circuit per bucket.
+//!
+//! A circuit verifies signatures on messages of a fixed length. Messages go to the smallest
+//! bucket they fit in, and are zero padded to its length by the encoding of the public inputs,
+//! see [`encode_signature`]: the registry only picks the bucket. The length of the message is
+//! part of the public input of the bucket circuit, so that a message and its padded version
+//! cannot be confused.
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{bail, Result};
+use hap_verifier::{encoding::encode_signature, Encoding, FsParameters, SignatureCircuit};
+use log::info;
+use once_cell::sync::OnceCell;
+use snarkvm_algorithms::{
+    polycommit::kzg10::UniversalParams,
+    snark::varuna::{self, CircuitProvingKey, CircuitVerifyingKey, SNARKMode, VarunaHidingMode},
+};
+use snarkvm_circuit::Circuit;
+use snarkvm_curves::bls12_377::{Bls12_377, Fr};
+use tracing::info_span;
+
+use crate::api::{self, ProveJob, ProverConfig};
+use crate::bundle::ProofBundle;
+use crate::input::SignatureInput;
+use crate::TestCase;
+
+/// A circuit for messages of at most `max_len` bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LengthBucket {
+    pub max_len: usize,
+    pub test_case: TestCase,
+    /// Layout of the public inputs of the circuit.
+    pub encoding: Encoding,
+}
+
+/// The length buckets, by increasing length.
+#[derive(Debug, Clone)]
+pub struct BucketRegistry {
+    buckets: Vec<LengthBucket>,
+}
+
+impl Default for BucketRegistry {
+    /// The buckets of the benchmarks: 100, 1,000 and 50,000 bytes.
+    fn default() -> Self {
+        Self::new(vec![
+            LengthBucket {
+                max_len: 100,
+                test_case: TestCase::Test1,
+                encoding: Encoding::Bytes,
+            },
+            LengthBucket {
+                max_len: 1000,
+                test_case: TestCase::Test2,
+                encoding: Encoding::Bytes,
+            },
+            LengthBucket {
+                max_len: 50_000,
+                test_case: TestCase::Test3,
+                encoding: Encoding::Bytes,
+            },
+        ])
+    }
+}
+
+impl BucketRegistry {
+    pub fn new(mut buckets: Vec<LengthBucket>) -> Self {
+        buckets.sort_by_key(|bucket| bucket.max_len);
+        Self { buckets }
+    }
+
+    /// Uses `encoding` for the public inputs of all the buckets.
+    pub fn with_encoding(mut self, encoding: Encoding) -> Self {
+        for bucket in &mut self.buckets {
+            bucket.encoding = encoding;
+        }
+        self
+    }
+
+    pub fn buckets(&self) -> &[LengthBucket] {
+        &self.buckets
+    }
+
+    /// The smallest bucket which fits a message of `len` bytes.
+    pub fn bucket_for(&self, len: usize) -> Result<LengthBucket> {
+        match self.buckets.iter().find(|bucket| len <= bucket.max_len) {
+            Some(bucket) => Ok(*bucket),
+            None => bail!("no circuit for messages of {len} bytes"),
+        }
+    }
+
+    /// Groups the messages by bucket. Returns the indices of the messages in each bucket, by
+    /// increasing bucket length.
+    pub fn group<M: AsRef<[u8]>>(&self, msgs: &[M]) -> Result<Vec<(LengthBucket, Vec<usize>)>> {
+        let mut groups = BTreeMap::<usize, (LengthBucket, Vec<usize>)>::new();
+        for (index, msg) in msgs.iter().enumerate() {
+            let bucket = self.bucket_for(msg.as_ref().len())?;
+            groups
+                .entry(bucket.max_len)
+                .or_insert_with(|| (bucket, vec![]))
+                .1
+                .push(index);
+        }
+        Ok(groups.into_values().collect())
+    }
+}
+
+/// The keys of a circuit, for proofs of the hiding mode `SM`.
+pub type CircuitKeys<SM = VarunaHidingMode> = (
+    CircuitProvingKey<Bls12_377, SM>,
+    CircuitVerifyingKey<Bls12_377>,
+);
+
+/// The keys of each bucket circuit for the hiding mode `SM`, compiled on first use by the
+/// provers. The verifiers only use the keys inserted or compiled before, see
+/// [`KeyCache::require`].
+pub struct KeyCache<'a, SM: SNARKMode = VarunaHidingMode> {
+    urs: &'a UniversalParams<Bls12_377>,
+    /* The lock only guards the map: a circuit is compiled in its cell, outside of the lock, so
+     * that the keys of the other circuits can be used or compiled meanwhile */
+    keys: Mutex<HashMap<(TestCase, Encoding), Arc<OnceCell<Arc<CircuitKeys<SM>>>>>>,
+}
+
+impl<'a, SM: SNARKMode> KeyCache<'a, SM> {
+    pub fn new(urs: &'a UniversalParams<Bls12_377>) -> Self {
+        Self {
+            urs,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Adds keys compiled beforehand, e.g. read from files.
+    pub fn insert(&self, test_case: TestCase, encoding: Encoding, keys: CircuitKeys<SM>) {
+        self.keys.lock().unwrap().insert(
+            (test_case, encoding),
+            Arc::new(OnceCell::with_value(Arc::new(keys))),
+        );
+    }
+
+    /// The keys of a test case, if they were compiled or inserted already.
+    pub fn loaded(&self, test_case: TestCase, encoding: Encoding) -> Option<Arc<CircuitKeys<SM>>> {
+        self.keys
+            .lock()
+            .unwrap()
+            .get(&(test_case, encoding))
+            .and_then(|cell| cell.get().cloned())
+    }
+
+    /// Like [`KeyCache::loaded`], but fails if the keys were neither compiled nor inserted.
+    pub fn require(&self, test_case: TestCase, encoding: Encoding) -> Result<Arc<CircuitKeys<SM>>> {
+        match self.loaded(test_case, encoding) {
+            Some(keys) => Ok(keys),
+            None => bail!(
+                "no keys loaded for {} ({} encoding)",
+                test_case.name(),
+                encoding.name()
+            ),
+        }
+    }
+
+    pub fn urs(&self) -> &'a UniversalParams<Bls12_377> {
+        self.urs
+    }
+
+    /// The keys of the circuit of `bucket`, compiled once if they are not loaded yet.
+    pub fn get(&self, bucket: &LengthBucket) -> Result<Arc<CircuitKeys<SM>>> {
+        let cell = self
+            .keys
+            .lock()
+            .unwrap()
+            .entry((bucket.test_case, bucket.encoding))
+            .or_default()
+            .clone();
+        let keys = cell.get_or_try_init(|| {
+            api::compile_with_encoding::<Circuit, SM>(bucket.test_case, bucket.encoding, self.urs)
+                .map(Arc::new)
+        })?;
+        Ok(keys.clone())
+    }
+}
+
+/// The signatures of one bucket in a [`BucketProof`].
+pub struct BucketGroup {
+    pub bucket: LengthBucket,
+    /// Indices of the signatures in the input.
+    pub signatures: Vec<usize>,
+    /// Public inputs of the instances, in the order of `signatures`.
+    pub inputs: Vec<Vec<Fr>>,
+}
+
+/// A single proof over messages of mixed lengths.
+pub struct BucketProof {
+    pub proof: varuna::Proof<Bls12_377>,
+    pub groups: Vec<BucketGroup>,
+}
+
+impl BucketProof {
+    /// The proof bundle, with one job per bucket named after its circuit, see
+    /// [`TestCase::circuit_name`].
+    pub fn bundle(&self) -> Result<ProofBundle> {
+        let jobs = self
+            .groups
+            .iter()
+            .map(|group| {
+                let bucket = &group.bucket;
+                (
+                    bucket.test_case.circuit_name(bucket.encoding),
+                    &group.inputs[..],
+                )
+            })
+            .collect::<Vec<_>>();
+        let jobs = jobs
+            .iter()
+            .map(|(name, inputs)| (name.as_str(), *inputs))
+            .collect::<Vec<_>>();
+        ProofBundle::new(&self.proof, &jobs)
+    }
+}
+
+/// Proves signatures on messages of mixed lengths in one batch proof, with one job per bucket.
+///
+/// Fails before proving if the public inputs of a circuit do not match the encoding of its
+/// signature, see [`hap_verifier::encoding`], so that the proof can be checked with
+/// [`verify_signatures`].
+pub fn prove<SM: SNARKMode>(
+    registry: &BucketRegistry,
+    keys: &KeyCache<SM>,
+    signatures: &[SignatureInput],
+    config: &ProverConfig,
+) -> Result<BucketProof> {
+    let msgs = signatures
+        .iter()
+        .map(|signature| &signature.message)
+        .collect::<Vec<_>>();
+    let groups = registry.group(&msgs)?;
+    let circuit_keys = groups
+        .iter()
+        .map(|(bucket, _)| keys.get(bucket))
+        .collect::<Result<Vec<_>>>()?;
+    let group_signatures = groups
+        .iter()
+        .map(|(bucket, indices)| {
+            info!(
+                "bucket of {} bytes: {} signatures",
+                bucket.max_len,
+                indices.len()
+            );
+            indices
+                .iter()
+                .map(|index| signatures[*index].clone())
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let jobs = groups
+        .iter()
+        .zip(&circuit_keys)
+        .zip(&group_signatures)
+        .map(|(((bucket, _), circuit_keys), signatures)| ProveJob {
+            test_case: bucket.test_case,
+            pk: &circuit_keys.0,
+            batch_num: signatures.len() as u32,
+            signatures: Some(signatures),
+            encoding: bucket.encoding,
+        })
+        .collect::<Vec<_>>();
+    let _span = info_span!(
+        "prove",
+        num_jobs = jobs.len(),
+        num_instances = signatures.len()
+    )
+    .entered();
+    let assignments = api::generate_jobs::<Circuit, SM>(&jobs, config);
+    let inputs = assignments.inputs();
+
+    for (((bucket, indices), signatures), inputs) in
+        groups.iter().zip(&group_signatures).zip(&inputs)
+    {
+        for ((index, signature), inputs) in indices.iter().zip(signatures).zip(inputs) {
+            if *inputs != encode_signature(signature, bucket.max_len, bucket.encoding)? {
+                bail!(
+                    "the public inputs of signature {index} do not match its encoding in the circuit of {}",
+                    bucket.test_case.name()
+                );
+            }
+        }
+    }
+    let proof = api::prove_assignments::<Circuit, SM>(keys.urs, &assignments, config);
+
+    let groups = groups
+        .into_iter()
+        .zip(inputs)
+        .map(|((bucket, signatures), inputs)| BucketGroup {
+            bucket,
+            signatures,
+            inputs,
+        })
+        .collect();
+    Ok(BucketProof { proof, groups })
+}
+
+/// Verifies a proof of `signatures` made by [`prove`], computing the public inputs from the
+/// signatures instead of using the ones of the prover. `fs_parameters` are the ones of the
+/// prover, see [`ProverConfig::fiat_shamir`].
+///
+/// The keys are never compiled here, fails if the keys of a bucket are not loaded.
+pub fn verify_signatures<SM: SNARKMode>(
+    registry: &BucketRegistry,
+    keys: &KeyCache<SM>,
+    fs_parameters: &FsParameters,
+    proof: &varuna::Proof<Bls12_377>,
+    signatures: &[SignatureInput],
+) -> Result<bool> {
+    let msgs = signatures
+        .iter()
+        .map(|signature| &signature.message)
+        .collect::<Vec<_>>();
+    let circuits = registry
+        .group(&msgs)?
+        .iter()
+        .map(|(bucket, _)| -> Result<_> {
+            Ok(SignatureCircuit {
+                max_len: bucket.max_len,
+                encoding: bucket.encoding,
+                vk: keys.require(bucket.test_case, bucket.encoding)?.1.clone(),
+            })
+        })
+        .collect::<Result<Vec<_>>>()?;
+    hap_verifier::verify_signatures::<SM>(keys.urs, fs_parameters, &circuits, proof, signatures)
+}
+
+/// Verifies a proof over messages of mixed lengths, with the Fiat-Shamir parameters of the
+/// prover, see [`ProverConfig::fiat_shamir`].
+///
+/// Like [`verify_signatures`], returns whether the proof is valid, and fails if the keys of a
+/// bucket are not loaded.
+pub fn verify<SM: SNARKMode>(
+    keys: &KeyCache<SM>,
+    fs_parameters: &FsParameters,
+    proof: &BucketProof,
+) -> Result<bool> {
+    let circuit_keys = proof
+        .groups
+        .iter()
+        .map(|group| keys.require(group.bucket.test_case, group.bucket.encoding))
+        .collect::<Result<Vec<_>>>()?;
+    let jobs = proof
+        .groups
+        .iter()
+        .zip(&circuit_keys)
+        .map(|(group, circuit_keys)| (&circuit_keys.1, &group.inputs[..]))
+        .collect::<Vec<_>>();
+    api::check_jobs::<SM>(keys.urs, fs_parameters, &proof.proof, &jobs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group() -> Result<()> {
+        let registry = BucketRegistry::default();
+        let msgs = [vec![0u8; 100], vec![0; 101], vec![0; 3], vec![0; 50_000]];
+
+        let groups = registry.group(&msgs)?;
+        let indices = groups
+            .iter()
+            .map(|(bucket, indices)| (bucket.max_len, indices.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            indices,
+            vec![(100, vec![0, 2]), (1000, vec![1]), (50_000, vec![3])]
+        );
+
+        assert_eq!(registry.bucket_for(3)?.test_case, TestCase::Test1);
+
+        assert!(registry.bucket_for(50_001).is_err());
+        Ok(())
+    }
+}
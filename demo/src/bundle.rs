@@ -0,0 +1,18 @@
+//! File formats of the command line tools: keys, universal parameters and proof bundles.
+//!
+//! Proof bundles are defined in the `hap-verifier` crate, so that they can be verified without
+//! this crate.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use snarkvm_utilities::ToBytes;
+
+pub use hap_verifier::bundle::{read_bytes, BundleJob, ProofBundle};
+
+/// Writes a value with `ToBytes`.
+pub fn write_bytes(path: impl AsRef<Path>, value: &impl ToBytes) -> Result<()> {
+    let path = path.as_ref();
+    fs::write(path, value.to_bytes_le()?).with_context(|| format!("failed to write {path:?}"))
+}
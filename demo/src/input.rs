@@ -0,0 +1,60 @@
+//! Lists of signatures to prove, read from JSON or CSV files.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+pub use hap_verifier::input::SignatureInput;
+
+/// Reads a list of signatures.
+///
+/// A `.json` file holds an array of [`SignatureInput`]. A `.csv` file has one signature per line,
+/// as `public_key,message,signature` in hex, with an optional header line.
+pub fn read_signatures(path: impl AsRef<Path>) -> Result<Vec<SignatureInput>> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path).with_context(|| format!("failed to read {path:?}"))?;
+
+    let signatures = match path.extension().and_then(|extension| extension.to_str()) {
+        Some("json") => serde_json::from_str::<Vec<SignatureInput>>(&content)
+            .with_context(|| format!("failed to parse {path:?}"))?,
+        Some("csv") => parse_csv(&content).with_context(|| format!("failed to parse {path:?}"))?,
+        _ => bail!("unknown format of {path:?}, expected a .json or .csv file"),
+    };
+
+    for (index, signature) in signatures.iter().enumerate() {
+        signature
+            .validate()
+            .with_context(|| format!("invalid signature {index} in {path:?}"))?;
+    }
+    Ok(signatures)
+}
+
+fn parse_csv(content: &str) -> Result<Vec<SignatureInput>> {
+    content
+        .lines()
+        .enumerate()
+        .map(|(number, line)| (number, line.trim()))
+        .filter(|(number, line)| {
+            !line.is_empty() && !(*number == 0 && line.starts_with("public_key"))
+        })
+        .map(|(number, line)| {
+            let fields = line.split(',').map(str::trim).collect::<Vec<_>>();
+            let [public_key, message, signature] = fields[..] else {
+                bail!(
+                    "line {}: expected 3 fields, got {}",
+                    number + 1,
+                    fields.len()
+                );
+            };
+            Ok(SignatureInput {
+                public_key: hex::decode(public_key)
+                    .with_context(|| format!("line {}: invalid public key", number + 1))?,
+                message: hex::decode(message)
+                    .with_context(|| format!("line {}: invalid message", number + 1))?,
+                signature: hex::decode(signature)
+                    .with_context(|| format!("line {}: invalid signature", number + 1))?,
+            })
+        })
+        .collect()
+}
@@ -13,41 +13,99 @@
 // limitations under the License.
 
 use std::collections::BTreeMap;
+use std::str::FromStr;
 
-use aleo_std_profiler::{end_timer, start_timer};
+use anyhow::bail;
+use hap_verifier::Encoding;
 use log::info;
 use snarkvm_algorithms::{
     polycommit::kzg10::UniversalParams,
-    snark::varuna::{CircuitProvingKey, CircuitVerifyingKey, VarunaHidingMode},
+    snark::varuna::{CircuitProvingKey, CircuitVerifyingKey, SNARKMode},
 };
+use snarkvm_circuit::Circuit;
 use snarkvm_curves::bls12_377::Bls12_377;
+use tracing::info_span;
 
 pub mod api;
+pub mod batch;
+pub mod bucket;
+pub mod bundle;
+pub mod input;
+pub mod params;
 pub mod r1cs_provider;
+pub mod report;
+pub mod server;
+pub mod trace;
 
 /// We have define a enum to control which the circuits to run
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum TestCase {
     Test1,
     Test2,
     Test3,
 }
 
-pub fn prove_and_verify(
+impl TestCase {
+    pub const ALL: [TestCase; 3] = [TestCase::Test1, TestCase::Test2, TestCase::Test3];
+
+    /// The name of the test case, as passed to gnark-circuit-gen.
+    pub fn name(&self) -> &'static str {
+        match self {
+            TestCase::Test1 => "test1",
+            TestCase::Test2 => "test2",
+            TestCase::Test3 => "test3",
+        }
+    }
+
+    /// The name of the circuit of the test case with public inputs laid out as `encoding`, as
+    /// used for key files and proof bundles, e.g. `test1` or `test1-packed`.
+    pub fn circuit_name(&self, encoding: Encoding) -> String {
+        match encoding {
+            Encoding::Bytes => self.name().to_string(),
+            _ => format!("{}-{}", self.name(), encoding.name()),
+        }
+    }
+
+    /// The inverse of [`TestCase::circuit_name`].
+    pub fn parse_circuit_name(name: &str) -> anyhow::Result<(TestCase, Encoding)> {
+        match name.split_once('-') {
+            Some((test_case, encoding)) => Ok((test_case.parse()?, encoding.parse()?)),
+            None => Ok((name.parse()?, Encoding::Bytes)),
+        }
+    }
+}
+
+impl FromStr for TestCase {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match TestCase::ALL.iter().find(|test_case| test_case.name() == s) {
+            Some(test_case) => Ok(*test_case),
+            None => bail!("unknown test case {s:?}, expected one of test1, test2 or test3"),
+        }
+    }
+}
+
+/// Proves a batch of `batch_num` random signatures and verifies the proof, and reports the time
+/// spent in each phase, see [`report`]. With the `profiler` feature, the report also has the
+/// rounds of the prover, read from the output of `aleo_std_profiler`.
+pub fn prove_and_verify<SM: SNARKMode>(
     test_case: TestCase,
     urs: &UniversalParams<Bls12_377>,
-    circuit_keys: &(
-        CircuitProvingKey<Bls12_377, VarunaHidingMode>,
-        CircuitVerifyingKey<Bls12_377>,
-    ),
+    circuit_keys: &(CircuitProvingKey<Bls12_377, SM>, CircuitVerifyingKey<Bls12_377>),
     batch_num: u32,
-) {
+) -> report::Report {
     info!("prove_and_verify for {test_case:?}");
+    let _span = info_span!("prove_and_verify", test_case = test_case.name(), batch_num).entered();
+    let recording = report::Recording::start();
 
     let pk = &circuit_keys.0;
-    let prove_time = start_timer!(|| format!("Generate proof for a batch with size {batch_num}"));
-    let (proof, inputs) = api::prove(test_case, urs, &pk, batch_num);
-    end_timer!(prove_time);
+    let config = api::ProverConfig::default();
+    let prove = || api::prove(test_case, urs, pk, batch_num, &config);
+    #[cfg(all(feature = "profiler", unix))]
+    let ((proof, inputs), profiler_output) = report::capture_stdout(prove);
+    #[cfg(not(all(feature = "profiler", unix)))]
+    let ((proof, inputs), profiler_output) = (prove(), String::new());
 
     /* Prepare vks_to_inputs for verifier */
     let mut vks_to_inputs = BTreeMap::new();
@@ -56,38 +114,10 @@ pub fn prove_and_verify(
     vks_to_inputs.insert(vk, &inputs[..]);
 
     // Note: proof verification should take negligible time,
-    let verify_time = start_timer!(|| format!("Verify proof for a batch with size {batch_num}"));
-    api::verify_proof(urs, &proof, &vks_to_inputs);
-    end_timer!(verify_time);
-}
+    let fs_parameters = config.fiat_shamir::<Circuit>();
+    api::verify_proof::<SM>(urs, fs_parameters, &proof, &vks_to_inputs);
 
-#[cfg(test)]
-mod tests {
-
-    use anyhow::Result;
-
-    #[test]
-    fn download_tons_of_blobs() -> Result<()> {
-        snarkvm_parameters::testnet3::Degree16::load_bytes()?;
-        snarkvm_parameters::testnet3::Degree17::load_bytes()?;
-        snarkvm_parameters::testnet3::Degree18::load_bytes()?;
-        snarkvm_parameters::testnet3::Degree19::load_bytes()?;
-        snarkvm_parameters::testnet3::Degree20::load_bytes()?;
-        snarkvm_parameters::testnet3::Degree21::load_bytes()?;
-        snarkvm_parameters::testnet3::Degree22::load_bytes()?;
-        snarkvm_parameters::testnet3::Degree23::load_bytes()?;
-        snarkvm_parameters::testnet3::Degree24::load_bytes()?;
-
-        snarkvm_parameters::testnet3::ShiftedDegree16::load_bytes()?;
-        snarkvm_parameters::testnet3::ShiftedDegree17::load_bytes()?;
-        snarkvm_parameters::testnet3::ShiftedDegree18::load_bytes()?;
-        snarkvm_parameters::testnet3::ShiftedDegree19::load_bytes()?;
-        snarkvm_parameters::testnet3::ShiftedDegree20::load_bytes()?;
-        snarkvm_parameters::testnet3::ShiftedDegree21::load_bytes()?;
-        snarkvm_parameters::testnet3::ShiftedDegree22::load_bytes()?;
-        snarkvm_parameters::testnet3::ShiftedDegree23::load_bytes()?;
-        snarkvm_parameters::testnet3::ShiftedDegree24::load_bytes()?;
-
-        Ok(())
-    }
+    let mut report = recording.finish();
+    report.add_profiler_output(&profiler_output);
+    report
 }
@@ -0,0 +1,424 @@
+//! Offline management of the universal SRS blobs of snarkVM.
+//!
+//! The universal setup loads the powers of beta of the Aleo SRS from files which snarkVM
+//! downloads on first use into `~/.aleo/resources`. `DegreeN` holds the powers up to `2^N` and
+//! `ShiftedDegreeN` the matching shifted powers, and only the blobs of degree 15 ship with the
+//! crate. On machines without network access, the blobs are imported here from a directory or a
+//! tar archive (optionally gzipped) and checked against the checksums pinned by snarkVM, so
+//! that the setup fails early with a clear message instead of a download error.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Largest degree of the blobs which ship with snarkVM.
+pub const BUNDLED_DEGREE: usize = 15;
+
+/// Largest degree of the Aleo SRS.
+pub const MAX_DEGREE: usize = 28;
+
+/// The powers of beta, or the shifted powers of beta.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum BlobKind {
+    Powers,
+    ShiftedPowers,
+}
+
+/// A blob of the SRS, `powers-of-beta-<degree>.usrs` or `shifted-powers-of-beta-<degree>.usrs`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Blob {
+    pub kind: BlobKind,
+    pub degree: usize,
+}
+
+/// The size and checksum of a blob, from the `.metadata` files of snarkVM.
+#[derive(Debug, Deserialize)]
+struct Metadata {
+    checksum: String,
+    size: u64,
+}
+
+macro_rules! metadata {
+    ($($fname:literal),* $(,)?) => {
+        &[$(
+            (
+                $fname,
+                include_str!(concat!(
+                    env!("CARGO_MANIFEST_DIR"),
+                    "/../snarkVM/parameters/src/testnet3/resources/",
+                    $fname,
+                    ".metadata"
+                )),
+            )
+        ),*]
+    };
+}
+
+static METADATA: &[(&str, &str)] = metadata![
+    "powers-of-beta-16",
+    "powers-of-beta-17",
+    "powers-of-beta-18",
+    "powers-of-beta-19",
+    "powers-of-beta-20",
+    "powers-of-beta-21",
+    "powers-of-beta-22",
+    "powers-of-beta-23",
+    "powers-of-beta-24",
+    "powers-of-beta-25",
+    "powers-of-beta-26",
+    "powers-of-beta-27",
+    "powers-of-beta-28",
+    "shifted-powers-of-beta-16",
+    "shifted-powers-of-beta-17",
+    "shifted-powers-of-beta-18",
+    "shifted-powers-of-beta-19",
+    "shifted-powers-of-beta-20",
+    "shifted-powers-of-beta-21",
+    "shifted-powers-of-beta-22",
+    "shifted-powers-of-beta-23",
+    "shifted-powers-of-beta-24",
+    "shifted-powers-of-beta-25",
+    "shifted-powers-of-beta-26",
+    "shifted-powers-of-beta-27",
+    "shifted-powers-of-beta-28",
+];
+
+impl Blob {
+    /// All the blobs which are downloaded by snarkVM, by kind and increasing degree.
+    pub fn all() -> Vec<Blob> {
+        [BlobKind::Powers, BlobKind::ShiftedPowers]
+            .into_iter()
+            .flat_map(|kind| {
+                (BUNDLED_DEGREE + 1..=MAX_DEGREE).map(move |degree| Blob { kind, degree })
+            })
+            .collect()
+    }
+
+    /// The name of the blob, without extension.
+    pub fn name(&self) -> String {
+        match self.kind {
+            BlobKind::Powers => format!("powers-of-beta-{}", self.degree),
+            BlobKind::ShiftedPowers => format!("shifted-powers-of-beta-{}", self.degree),
+        }
+    }
+
+    fn metadata(&self) -> Metadata {
+        let name = self.name();
+        let (_, metadata) = METADATA
+            .iter()
+            .find(|(fname, _)| *fname == name)
+            .expect("every blob has metadata");
+        serde_json::from_str(metadata).expect("the metadata of snarkVM is well formed")
+    }
+
+    /// The SHA-256 checksum of the blob, in hex.
+    pub fn checksum(&self) -> String {
+        self.metadata().checksum
+    }
+
+    /// The size of the blob in bytes.
+    pub fn size(&self) -> u64 {
+        self.metadata().size
+    }
+
+    /// The name of the file snarkVM looks for, `<name>.usrs.<first 7 digits of the checksum>`.
+    pub fn file_name(&self) -> String {
+        format!("{}.usrs.{}", self.name(), &self.checksum()[..7])
+    }
+
+    /// Whether a file of an import source holds this blob: `<name>.usrs`, optionally followed
+    /// by a suffix such as the one of [`Blob::file_name`].
+    fn matches(&self, file_name: &str) -> bool {
+        let prefix = format!("{}.usrs", self.name());
+        file_name == prefix || file_name.starts_with(&format!("{prefix}."))
+    }
+
+    /// Checks the content of the blob read from `reader`, copying it to `copy` if any. The
+    /// largest blobs are several GB, so they are streamed rather than loaded in memory.
+    pub fn check(&self, reader: &mut dyn Read, mut copy: Option<&mut dyn Write>) -> Result<()> {
+        let metadata = self.metadata();
+        let mut hasher = Sha256::new();
+        let mut size = 0u64;
+        let mut buffer = vec![0u8; 1 << 20];
+        loop {
+            let read = reader.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            size += read as u64;
+            ensure!(
+                size <= metadata.size,
+                "{} has more than the expected {} bytes",
+                self.name(),
+                metadata.size
+            );
+            hasher.update(&buffer[..read]);
+            if let Some(copy) = copy.as_mut() {
+                copy.write_all(&buffer[..read])?;
+            }
+        }
+        ensure!(
+            size == metadata.size,
+            "{} has {size} bytes, expected {}",
+            self.name(),
+            metadata.size
+        );
+        let checksum = hex::encode(hasher.finalize());
+        ensure!(
+            checksum == metadata.checksum,
+            "{} has checksum {checksum}, expected {}",
+            self.name(),
+            metadata.checksum
+        );
+        Ok(())
+    }
+}
+
+impl fmt::Display for Blob {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// The smallest `N` such that the powers up to `2^N` cover polynomials of `max_degree`.
+pub fn required_degree(max_degree: usize) -> usize {
+    (max_degree + 1).next_power_of_two().trailing_zeros() as usize
+}
+
+/// The blobs the universal setup loads for polynomials of `max_degree`, see
+/// [`crate::api::max_degree`]. Empty if the bundled blobs are enough.
+pub fn required_blobs(max_degree: usize) -> Result<Vec<Blob>> {
+    let degree = required_degree(max_degree);
+    ensure!(
+        degree <= MAX_DEGREE,
+        "a maximum degree of {max_degree} needs powers up to 2^{degree}, the SRS stops at 2^{MAX_DEGREE}"
+    );
+    Ok(Blob::all()
+        .into_iter()
+        .filter(|blob| blob.degree <= degree)
+        .collect())
+}
+
+/// State of a blob in a [`ParamDir`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BlobStatus {
+    Present,
+    Missing,
+    /// The file exists but does not match the checksum, with the reason.
+    Corrupted(String),
+}
+
+/// The directory snarkVM loads the blobs from.
+#[derive(Debug, Clone)]
+pub struct ParamDir {
+    dir: PathBuf,
+}
+
+impl ParamDir {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// `~/.aleo/resources`, where snarkVM stores the blobs it downloads.
+    pub fn aleo() -> Result<Self> {
+        let home = std::env::var_os("HOME").context("HOME is not set")?;
+        Ok(Self::new(Path::new(&home).join(".aleo").join("resources")))
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn path(&self, blob: &Blob) -> PathBuf {
+        self.dir.join(blob.file_name())
+    }
+
+    /// Reads and checks the file of `blob`.
+    pub fn status(&self, blob: &Blob) -> Result<BlobStatus> {
+        let path = self.path(blob);
+        let mut file = match File::open(&path) {
+            Ok(file) => io::BufReader::new(file),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(BlobStatus::Missing),
+            Err(err) => return Err(err).with_context(|| format!("failed to open {path:?}")),
+        };
+        Ok(match blob.check(&mut file, None) {
+            Ok(()) => BlobStatus::Present,
+            Err(err) => BlobStatus::Corrupted(err.to_string()),
+        })
+    }
+
+    /// Fails unless all `blobs` are present and match their checksums, listing the missing or
+    /// corrupted ones.
+    ///
+    /// The universal setup only runs [`ParamDir::ensure_present`]: snarkVM would otherwise try to
+    /// download the missing blobs, which fails with an unhelpful error when offline.
+    pub fn ensure(&self, blobs: &[Blob]) -> Result<()> {
+        let mut problems = vec![];
+        for blob in blobs {
+            match self.status(blob)? {
+                BlobStatus::Present => {}
+                BlobStatus::Missing => problems.push(format!("{blob} is missing")),
+                BlobStatus::Corrupted(reason) => problems.push(reason),
+            }
+        }
+        self.report(problems)
+    }
+
+    /// Like [`ParamDir::ensure`], but only checks that the files of the blobs exist and have
+    /// their size, without reading them: the blobs take gigabytes, hashing them is left to
+    /// `ensure` when importing or verifying them.
+    pub fn ensure_present(&self, blobs: &[Blob]) -> Result<()> {
+        let mut problems = vec![];
+        for blob in blobs {
+            let path = self.path(blob);
+            match fs::metadata(&path) {
+                Ok(metadata) if metadata.len() == blob.size() => {}
+                Ok(metadata) => problems.push(format!(
+                    "{blob} has {} bytes instead of {}",
+                    metadata.len(),
+                    blob.size()
+                )),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                    problems.push(format!("{blob} is missing"))
+                }
+                Err(err) => return Err(err).with_context(|| format!("failed to read {path:?}")),
+            }
+        }
+        self.report(problems)
+    }
+
+    fn report(&self, problems: Vec<String>) -> Result<()> {
+        if !problems.is_empty() {
+            bail!(
+                "the SRS blobs in {:?} are incomplete: {}. Import them with `hap params import \
+                 <directory or archive>`, they are not downloaded when offline",
+                self.dir,
+                problems.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Imports the blobs found in `source`, a directory or a `.tar`, `.tar.gz` or `.tgz`
+    /// archive, and returns them. Fails without importing a blob whose checksum does not match.
+    pub fn import(&self, source: &Path) -> Result<Vec<Blob>> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("failed to create {:?}", self.dir))?;
+
+        let mut imported = vec![];
+        if source.is_dir() {
+            for entry in
+                fs::read_dir(source).with_context(|| format!("failed to read {source:?}"))?
+            {
+                let path = entry?.path();
+                if let Some(blob) = find_blob(&path) {
+                    let mut file =
+                        File::open(&path).with_context(|| format!("failed to open {path:?}"))?;
+                    self.write(&blob, &mut file)
+                        .with_context(|| format!("failed to import {path:?}"))?;
+                    imported.push(blob);
+                }
+            }
+        } else {
+            let file = File::open(source).with_context(|| format!("failed to open {source:?}"))?;
+            let name = source.to_string_lossy();
+            let reader: Box<dyn Read> = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+                Box::new(GzDecoder::new(file))
+            } else if name.ends_with(".tar") {
+                Box::new(file)
+            } else {
+                bail!("unknown format of {source:?}, expected a directory or a .tar, .tar.gz or .tgz archive");
+            };
+            let mut archive = tar::Archive::new(reader);
+            for entry in archive
+                .entries()
+                .with_context(|| format!("failed to read {source:?}"))?
+            {
+                let mut entry = entry?;
+                let path = entry.path()?.into_owned();
+                if let Some(blob) = find_blob(&path) {
+                    self.write(&blob, &mut entry)
+                        .with_context(|| format!("failed to import {path:?} from {source:?}"))?;
+                    imported.push(blob);
+                }
+            }
+        }
+        imported.sort();
+        imported.dedup();
+        Ok(imported)
+    }
+
+    /// Copies the blob read from `reader` to a temporary file, and moves it under its final
+    /// name once checked.
+    fn write(&self, blob: &Blob, reader: &mut dyn Read) -> Result<()> {
+        let path = self.path(blob);
+        let tmp = path.with_extension("tmp");
+        let mut file = File::create(&tmp).with_context(|| format!("failed to create {tmp:?}"))?;
+        if let Err(err) = blob.check(reader, Some(&mut file)) {
+            let _ = fs::remove_file(&tmp);
+            return Err(err);
+        }
+        fs::rename(&tmp, &path).with_context(|| format!("failed to write {path:?}"))
+    }
+}
+
+fn find_blob(path: &Path) -> Option<Blob> {
+    let file_name = path.file_name()?.to_str()?;
+    Blob::all().into_iter().find(|blob| blob.matches(file_name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_required_blobs() -> Result<()> {
+        assert!(required_blobs((1 << 15) - 1)?.is_empty());
+
+        let blobs = required_blobs(1 << 17)?;
+        assert_eq!(blobs.len(), 6);
+        assert_eq!(
+            blobs[0].file_name(),
+            format!("powers-of-beta-16.usrs.{}", &blobs[0].checksum()[..7])
+        );
+        assert_eq!(blobs[5].name(), "shifted-powers-of-beta-18");
+
+        assert!(required_blobs(1 << 28).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_import() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let params = ParamDir::new(dir.path());
+        let blob = Blob {
+            kind: BlobKind::Powers,
+            degree: 16,
+        };
+        let err = params.ensure(&[blob]).unwrap_err().to_string();
+        assert!(err.contains("powers-of-beta-16 is missing"), "{err}");
+        let err = params.ensure_present(&[blob]).unwrap_err().to_string();
+        assert!(err.contains("powers-of-beta-16 is missing"), "{err}");
+        fs::write(params.path(&blob), b"truncated")?;
+        let err = params.ensure_present(&[blob]).unwrap_err().to_string();
+        assert!(err.contains("has 9 bytes"), "{err}");
+        fs::remove_file(params.path(&blob))?;
+
+        /* a blob with the wrong content is rejected */
+        let source = tempfile::tempdir()?;
+        fs::write(
+            source.path().join("powers-of-beta-16.usrs"),
+            b"not the powers",
+        )?;
+        fs::write(source.path().join("README"), b"ignored")?;
+        assert!(params.import(source.path()).is_err());
+        assert_eq!(params.status(&blob)?, BlobStatus::Missing);
+        Ok(())
+    }
+}
@@ -0,0 +1,248 @@
+//! Balancing of the non-zero entries between the A and B matrices.
+//!
+//! For a constraint `a * b = c` the linear combinations `a` and `b` can be swapped freely, which
+//! moves non-zero entries between the A and B matrices. Varuna sizes the domain of each matrix
+//! after its number of non-zeros, so the choice of which rows to swap changes the prover cost.
+//!
+//! Lookup constraints are never swapped, their non-zeros are only counted into A since B and C
+//! are all zeros for them.
+
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BalanceStrategy {
+    /// Keep `a` and `b` as exported by gnark.
+    None,
+    /// Decide per constraint, in order, from a running count of non-zeros.
+    #[default]
+    Greedy,
+    /// Partition all rows at once, assigning the largest `|len(a) - len(b)|` first to the
+    /// lighter matrix. The result is within `max |len(a) - len(b)|` of the optimal balance.
+    Partition,
+    /// Minimize the Varuna cost model directly: the size of the largest non-zero domain first,
+    /// then the sum of the non-zero domains of A, B and C, then `max(nnz)` of A and B.
+    VarunaCost,
+}
+
+/// Number of non-zeros per matrix after balancing.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct BalanceReport {
+    pub strategy: BalanceStrategy,
+    pub nnz_a: usize,
+    pub nnz_b: usize,
+    pub nnz_c: usize,
+    pub num_swapped: usize,
+}
+
+/// Decides for each row `(len(a), len(b))` whether `a` and `b` should be swapped.
+///
+/// `nnz_lookup_a` is the number of non-zeros the lookup constraints contribute to A.
+pub fn plan_swaps(
+    strategy: BalanceStrategy,
+    rows: &[(usize, usize)],
+    nnz_lookup_a: usize,
+    nnz_c: usize,
+) -> Vec<bool> {
+    match strategy {
+        BalanceStrategy::None => vec![false; rows.len()],
+        BalanceStrategy::Greedy => plan_greedy(rows, nnz_lookup_a),
+        BalanceStrategy::Partition => {
+            let base = Base::new(rows, nnz_lookup_a);
+            fill(rows, &base, base.total / 2)
+        }
+        BalanceStrategy::VarunaCost => plan_varuna_cost(rows, nnz_lookup_a, nnz_c),
+    }
+}
+
+/// Counts the non-zeros per matrix resulting from `swaps`.
+pub fn report(
+    strategy: BalanceStrategy,
+    rows: &[(usize, usize)],
+    swaps: &[bool],
+    nnz_lookup_a: usize,
+    nnz_c: usize,
+) -> BalanceReport {
+    let (nnz_a, nnz_b) = count(rows, swaps, nnz_lookup_a);
+    BalanceReport {
+        strategy,
+        nnz_a,
+        nnz_b,
+        nnz_c,
+        num_swapped: swaps.iter().filter(|swap| **swap).count(),
+    }
+}
+
+fn count(rows: &[(usize, usize)], swaps: &[bool], nnz_lookup_a: usize) -> (usize, usize) {
+    rows.iter()
+        .zip(swaps)
+        .fold((nnz_lookup_a, 0), |(a, b), (&(len_a, len_b), &swap)| {
+            if swap {
+                (a + len_b, b + len_a)
+            } else {
+                (a + len_a, b + len_b)
+            }
+        })
+}
+
+fn plan_greedy(rows: &[(usize, usize)], nnz_lookup_a: usize) -> Vec<bool> {
+    let (mut count_non_zero_a, mut count_non_zero_b) = (nnz_lookup_a, 0usize);
+    rows.iter()
+        .map(|&(len_a, len_b)| {
+            let swap = (len_a < len_b && count_non_zero_a < count_non_zero_b)
+                || (len_a > len_b && count_non_zero_a > count_non_zero_b);
+            if swap {
+                count_non_zero_a += len_b;
+                count_non_zero_b += len_a;
+            } else {
+                count_non_zero_a += len_a;
+                count_non_zero_b += len_b;
+            }
+            swap
+        })
+        .collect()
+}
+
+/// The part of the non-zeros that does not depend on the swaps.
+struct Base {
+    /// Non-zeros of A when every row puts its shorter side into A.
+    a: usize,
+    /// Non-zeros of B when every row puts its shorter side into B.
+    b: usize,
+    /// Non-zeros of A and B together.
+    total: usize,
+    /// Indices of the rows with `len(a) != len(b)`, by decreasing `|len(a) - len(b)|`.
+    order: Vec<usize>,
+}
+
+impl Base {
+    fn new(rows: &[(usize, usize)], nnz_lookup_a: usize) -> Self {
+        let shorter = rows.iter().map(|&(a, b)| a.min(b)).sum::<usize>();
+        let total = rows.iter().map(|&(a, b)| a + b).sum::<usize>() + nnz_lookup_a;
+
+        let mut order = (0..rows.len())
+            .filter(|&i| rows[i].0 != rows[i].1)
+            .collect::<Vec<_>>();
+        // stable, so that rows with the same difference keep their original order
+        order.sort_by_key(|&i| std::cmp::Reverse(rows[i].0.abs_diff(rows[i].1)));
+
+        Self {
+            a: nnz_lookup_a + shorter,
+            b: shorter,
+            total,
+            order,
+        }
+    }
+}
+
+/// Assigns the differences, largest first, to the matrix with the most room left with respect to
+/// a target of `target` non-zeros for A (and the remaining ones for B).
+fn fill(rows: &[(usize, usize)], base: &Base, target: usize) -> Vec<bool> {
+    let target_a = target as i64;
+    let target_b = base.total as i64 - target_a;
+
+    let mut swaps = vec![false; rows.len()];
+    let (mut a, mut b) = (base.a as i64, base.b as i64);
+    for &i in &base.order {
+        let (len_a, len_b) = rows[i];
+        let diff = len_a.abs_diff(len_b) as i64;
+        let to_a = target_a - a >= target_b - b;
+        if to_a {
+            a += diff;
+        } else {
+            b += diff;
+        }
+        // the longer side goes into the matrix which receives the difference
+        swaps[i] = (len_a > len_b) != to_a;
+    }
+    swaps
+}
+
+fn domain_size(nnz: usize) -> usize {
+    nnz.max(1).next_power_of_two()
+}
+
+fn varuna_cost(a: usize, b: usize, c: usize) -> (usize, usize, usize) {
+    (
+        domain_size(a.max(b).max(c)),
+        domain_size(a) + domain_size(b) + domain_size(c),
+        a.max(b),
+    )
+}
+
+fn plan_varuna_cost(rows: &[(usize, usize)], nnz_lookup_a: usize, nnz_c: usize) -> Vec<bool> {
+    let base = Base::new(rows, nnz_lookup_a);
+    let (min_a, max_a) = (base.a, base.total - base.b);
+
+    // Besides the balanced split, the interesting targets are those which fill A or B exactly
+    // up to a power of two, since the cost model only changes at these points.
+    let mut targets = vec![base.total / 2];
+    let mut power = 1usize;
+    while power <= base.total {
+        targets.push(power);
+        targets.push(base.total.saturating_sub(power));
+        power *= 2;
+    }
+
+    targets
+        .into_iter()
+        .map(|target| target.clamp(min_a, max_a))
+        .map(|target| fill(rows, &base, target))
+        .min_by_key(|swaps| {
+            let (a, b) = count(rows, swaps, nnz_lookup_a);
+            varuna_cost(a, b, nnz_c)
+        })
+        .unwrap_or_else(|| vec![false; rows.len()])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ROWS: [(usize, usize); 8] = [
+        (3, 1),
+        (5, 1),
+        (1, 1),
+        (2, 7),
+        (4, 1),
+        (6, 2),
+        (1, 3),
+        (9, 1),
+    ];
+
+    fn nnz(strategy: BalanceStrategy, nnz_lookup_a: usize) -> BalanceReport {
+        let swaps = plan_swaps(strategy, &ROWS, nnz_lookup_a, 10);
+        report(strategy, &ROWS, &swaps, nnz_lookup_a, 10)
+    }
+
+    #[test]
+    fn test_strategies_preserve_total() {
+        for strategy in [
+            BalanceStrategy::None,
+            BalanceStrategy::Greedy,
+            BalanceStrategy::Partition,
+            BalanceStrategy::VarunaCost,
+        ] {
+            let report = nnz(strategy, 4);
+            assert_eq!(report.nnz_a + report.nnz_b, 4 + 48, "{strategy:?}");
+        }
+    }
+
+    #[test]
+    fn test_partition_is_balanced() {
+        let greedy = nnz(BalanceStrategy::Greedy, 4);
+        let partition = nnz(BalanceStrategy::Partition, 4);
+        assert!(partition.nnz_a.max(partition.nnz_b) <= greedy.nnz_a.max(greedy.nnz_b));
+        assert_eq!(partition.nnz_a.max(partition.nnz_b), 27);
+    }
+
+    #[test]
+    fn test_varuna_cost_is_not_worse() {
+        for nnz_lookup_a in [0, 4, 30] {
+            let cost =
+                |report: BalanceReport| varuna_cost(report.nnz_a, report.nnz_b, report.nnz_c);
+            let best = cost(nnz(BalanceStrategy::VarunaCost, nnz_lookup_a));
+            assert!(best <= cost(nnz(BalanceStrategy::Greedy, nnz_lookup_a)));
+            assert!(best <= cost(nnz(BalanceStrategy::Partition, nnz_lookup_a)));
+        }
+    }
+}
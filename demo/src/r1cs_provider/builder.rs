@@ -1,117 +1,321 @@
 use std::collections::HashMap;
-use std::convert::From;
+use std::convert::{From, TryFrom};
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
 
-use aleo_std_profiler::{end_timer, start_timer};
-use anyhow::Result;
-use scopeguard::defer;
+use anyhow::{anyhow, ensure, Result};
+use hap_verifier::Encoding;
+use log::debug;
+use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use snarkvm_algorithms::r1cs::LookupTable;
-use snarkvm_circuit::Circuit as Env;
 use snarkvm_circuit::Field;
-use snarkvm_circuit_environment::prelude::snarkvm_fields::Fp256;
+use snarkvm_circuit_environment::prelude::snarkvm_fields::{Fp256, Zero as _};
 use snarkvm_circuit_environment::prelude::PrimeField;
-use snarkvm_circuit_environment::{Environment as _, Inject as _, LinearCombination, Mode};
-use snarkvm_console_network::{Environment, Testnet3};
-use snarkvm_curves::bls12_377::FrParameters;
+use snarkvm_circuit_environment::{Environment, Inject as _, LinearCombination, Mode};
+use snarkvm_curves::bls12_377::{Fr, FrParameters};
 use snarkvm_utilities::BigInteger256;
+use tracing::{field, info_span};
 
+use crate::report::Phase;
+
+use super::balance::BalanceStrategy;
+use super::checker;
 use super::deserialize;
 use super::deserialize::BigInt;
+use super::optimize;
+use super::packing;
+use super::stats::{self, CircuitStats};
+
+/// The field of the circuits, the scalar field of BLS12-377 for all the networks of snarkVM.
+pub(crate) type EF = Fr;
+
+/// A linear combination whose coefficients are already converted into the field,
+/// sorted by variable index and without zero terms.
+type Terms = Vec<(usize, EF)>;
+
+impl TryFrom<&BigInt> for Fp256<FrParameters> {
+    type Error = anyhow::Error;
+
+    /// Fails if the value is not below the modulus.
+    fn try_from(value: &BigInt) -> Result<Self> {
+        Self::from_bigint(BigInteger256(value.0))
+            .ok_or_else(|| anyhow!("{:?} is not an element of the field", value.0))
+    }
+}
+
+impl From<&Fp256<FrParameters>> for BigInt {
+    fn from(value: &Fp256<FrParameters>) -> Self {
+        Self(value.to_bigint().0)
+    }
+}
+
+/// Options of the builder. The same options must be used for the circuit setup and for all the
+/// assignments proved with its keys.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BuildOptions {
+    /// How the non zeros are balanced between A and B.
+    pub balance: BalanceStrategy,
+    /// Run the optimization passes of [`optimize`] before building the circuit. The keys and the
+    /// proofs of the API are always built without it, see the [`optimize`] module.
+    pub optimize: bool,
+    /// Layout of the public inputs. A circuit exported with [`Encoding::Bytes`] is repacked
+    /// into [`Encoding::Packed`], see [`packing`].
+    pub encoding: Encoding,
+}
+
+/// What the builder computes once per circuit and reuses for all its assignments: the
+/// statistics, and which constraints have `a` and `b` swapped. It only depends on the constraints,
+/// which are the same for every assignment of a circuit.
+#[derive(Debug, Clone)]
+pub struct CircuitPlan {
+    pub stats: CircuitStats,
+    swaps: Vec<bool>,
+}
 
-type EF = <Testnet3 as Environment>::Field;
-type F = Field<Env>;
+impl CircuitPlan {
+    pub fn new(
+        r1cs: &deserialize::R1CS,
+        assignment: &deserialize::Assignment,
+        lookup: Option<&deserialize::Lookup>,
+        labels: Option<&deserialize::Labels>,
+        strategy: BalanceStrategy,
+    ) -> Result<Self> {
+        let swaps = stats::plan_swaps(strategy, r1cs, lookup);
+        let stats = CircuitStats::with_swaps(r1cs, assignment, lookup, labels, strategy, &swaps)?;
+        debug!("balance of non zeros: {:?}", stats.balance);
+        Ok(Self { stats, swaps })
+    }
 
-impl From<&BigInt> for Fp256<FrParameters> {
-    fn from(value: &BigInt) -> Self {
-        // Self(BigInteger256(value.0), PhantomData)
-        Self::from_bigint(BigInteger256(value.0)).unwrap()
+    /// Fails if the constraints and variables are not the ones of the circuit of the plan.
+    fn ensure_matches(
+        &self,
+        r1cs: &deserialize::R1CS,
+        assignment: &deserialize::Assignment,
+        lookup: Option<&deserialize::Lookup>,
+    ) -> Result<()> {
+        let stats = &self.stats;
+        let num_lookup_constraints = lookup.map_or(0, |lookup| lookup.constraints.len());
+        ensure!(
+            r1cs.0.len() == stats.num_constraints + stats.num_constant_rows
+                && num_lookup_constraints == stats.num_lookup_constraints
+                && assignment.num_public_inputs == stats.num_public
+                && assignment.variables.len() == stats.num_public + stats.num_private,
+            "the circuit does not match its plan: {} constraints, {} lookup constraints and {} \
+             variables, expected {}, {} and {}",
+            r1cs.0.len(),
+            num_lookup_constraints,
+            assignment.variables.len(),
+            stats.num_constraints + stats.num_constant_rows,
+            stats.num_lookup_constraints,
+            stats.num_public + stats.num_private
+        );
+        Ok(())
     }
 }
 
-pub(crate) fn construct_r1cs_from_file(
+/// What the builder reports about the circuit it built.
+#[derive(Debug, Clone)]
+pub struct BuildOutput {
+    pub plan: Arc<CircuitPlan>,
+    /// Labels of the constraints, in the order they were enforced in `Env`.
+    pub labels: Option<deserialize::Labels>,
+}
+
+/// Builds the circuit of the exported files in the circuit environment `Env` of a network, e.g.
+/// `snarkvm_circuit::Circuit` for Testnet3.
+///
+/// With the `plan` of a previous build of the same circuit, see [`BuildOutput::plan`], the
+/// statistics and the balancing are not computed again.
+pub(crate) fn construct_r1cs_from_file<Env: Environment<BaseField = EF>>(
     r1cs_file: impl AsRef<Path>,
     assignment_file: impl AsRef<Path>,
     lookup_file: Option<PathBuf>,
-) -> Result<()> {
-    let construct_time = start_timer!(|| "builder::construct_r1cs_from_file()");
-    defer! {
-        end_timer!(construct_time);
-    }
+    labels_file: Option<PathBuf>,
+    options: &BuildOptions,
+    plan: Option<Arc<CircuitPlan>>,
+) -> Result<BuildOutput> {
+    let span = info_span!(
+        "construct_r1cs_from_file",
+        optimize = options.optimize,
+        num_constraints = field::Empty
+    );
+    let _span = span.enter();
 
     let (r1cs, assignment, lookup) =
         deserialize::parse_file(r1cs_file, assignment_file, lookup_file)?;
+    let labels = labels_file.map(deserialize::parse_labels).transpose()?;
+    let output =
+        construct_r1cs_from_parsed::<Env>(r1cs, assignment, lookup, labels, options, plan)?;
+    span.record("num_constraints", output.plan.stats.num_constraints);
+    Ok(output)
+}
 
-    let fields = assignment
-        .variables
+/// Builds the circuit of the decoded files, see [`construct_r1cs_from_file`].
+///
+/// The files are not trusted: inconsistent sizes, variable indices out of range and values
+/// outside of the field are errors.
+pub fn construct_r1cs_from_parsed<Env: Environment<BaseField = EF>>(
+    r1cs: deserialize::R1CS,
+    assignment: deserialize::Assignment,
+    lookup: Option<deserialize::Lookup>,
+    labels: Option<deserialize::Labels>,
+    options: &BuildOptions,
+    plan: Option<Arc<CircuitPlan>>,
+) -> Result<BuildOutput> {
+    ensure!(
+        assignment.num_public_inputs <= assignment.variables.len(),
+        "{} public inputs for {} variables",
+        assignment.num_public_inputs,
+        assignment.variables.len()
+    );
+    if let Some(labels) = &labels {
+        labels.validate(
+            r1cs.0.len(),
+            lookup.as_ref().map_or(0, |lookup| lookup.constraints.len()),
+        )?;
+    }
+    let (r1cs, assignment, lookup, labels) = match (assignment.public_encoding, options.encoding) {
+        (Encoding::Bytes, Encoding::Packed) => packing::repack(r1cs, assignment, lookup, labels)?,
+        (exported, requested) => {
+            ensure!(
+                exported == requested,
+                "the public inputs are encoded as {exported:?}, expected {requested:?}"
+            );
+            (r1cs, assignment, lookup, labels)
+        }
+    };
+
+    let _phase = Phase::Synthesis.start();
+    let (r1cs, lookup, labels) = if options.optimize {
+        let optimized = optimize::optimize(&r1cs, lookup.as_ref(), assignment.num_public_inputs)?;
+        debug!("optimize: {:?}", optimized.report);
+        let labels = labels
+            .map(|labels| labels.select(&optimized.kept_constraints, &optimized.kept_lookups));
+        (optimized.r1cs, optimized.lookup, labels)
+    } else {
+        (r1cs, lookup, labels)
+    };
+
+    let plan = match plan {
+        Some(plan) => {
+            plan.ensure_matches(&r1cs, &assignment, lookup.as_ref())?;
+            plan
+        }
+        None => Arc::new(CircuitPlan::new(
+            &r1cs,
+            &assignment,
+            lookup.as_ref(),
+            labels.as_ref(),
+            options.balance,
+        )?),
+    };
+    construct_r1cs::<Env>(&r1cs, &assignment, lookup.as_ref(), &plan.swaps)?;
+    Ok(BuildOutput { plan, labels })
+}
+
+/// Builds the circuit in `Env` from the imported R1CS, assignment and lookup constraints, with
+/// `a` and `b` swapped in the constraints selected by `swaps`.
+pub(crate) fn construct_r1cs<Env: Environment<BaseField = EF>>(
+    r1cs: &deserialize::R1CS,
+    assignment: &deserialize::Assignment,
+    lookup: Option<&deserialize::Lookup>,
+    swaps: &[bool],
+) -> Result<()> {
+    /*
+     * The conversion of coefficients is independent for each constraint, so it is done in
+     * parallel, see `try_map`. `Field<Env>` is bound to the thread-local circuit and cannot be
+     * sent across threads, hence only the final construction and `Env::enforce` are done
+     * serially, in the original order of the constraints.
+     *
+     * Invariant: no rayon blocking (`par_iter`, `join`, `install`) after the first write into
+     * `Env`. While a worker waits for a parallel iterator, rayon may run another circuit synthesis
+     * on this thread, which resets the thread-local circuit when it is done, so all the conversion
+     * is done before `Env` is touched.
+     */
+    let num_variables = assignment.variables.len();
+    let convert = |_: usize, constraint: &deserialize::Constraint| {
+        convert_constraint(constraint, num_variables)
+    };
+    let converted = try_map(&r1cs.0, convert)?;
+    let converted_lookup = match lookup {
+        Some(lookup) => try_map(&lookup.constraints, convert)?,
+        None => vec![],
+    };
+    let values = checker::values(assignment)?;
+
+    ensure!(
+        swaps.len() == converted.len(),
+        "{} swaps for {} constraints",
+        swaps.len(),
+        converted.len()
+    );
+    /*
+     * snarkVM panics on a constraint between constants which does not hold, and does not add
+     * the ones which hold: such rows are checked here and skipped.
+     */
+    let constant_rows = converted
+        .iter()
+        .enumerate()
+        .map(|(index, (a, b, c))| -> Result<bool> {
+            let (Some(a), Some(b), Some(c)) =
+                (constant_value(a), constant_value(b), constant_value(c))
+            else {
+                return Ok(false);
+            };
+            ensure!(
+                a * b == c,
+                "constraint {index} does not hold, its terms are constants"
+            );
+            Ok(true)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let fields = values
         .iter()
         .enumerate()
-        .map(|(id, variable)| {
+        .map(|(id, value)| {
             if id == 0 {
                 // Insert the first element `1`
-                F::from(Env::one())
+                Field::<Env>::from(Env::one())
             } else {
-                F::new(
+                Field::<Env>::new(
                     if id < assignment.num_public_inputs {
                         Mode::Public
                     } else {
                         Mode::Private
                     },
-                    snarkvm_console::types::Field::new(EF::from(variable)),
+                    snarkvm_console::types::Field::new(*value),
                 )
             }
         })
         .collect::<Vec<_>>();
 
-    let func_convert_lc = |lc: &HashMap<usize, BigInt>| -> Result<_> {
-        // create Field<Env> from libsnark's linear_combination
-        let mut f: Field<Env> = F::from(Env::zero());
-        for term in lc {
-            let coeff = EF::from(term.1);
-            f += &F::from(LinearCombination::from(&fields[*term.0]) * (&coeff));
+    let func_convert_lc = |terms: &Terms| -> Result<_> {
+        // create Field<Env> from the pre-converted terms
+        let mut f = Field::<Env>::from(Env::zero());
+        for (index, coeff) in terms {
+            f += &Field::<Env>::from(LinearCombination::from(&fields[*index]) * coeff);
         }
         Ok(f)
     };
 
-    let (mut count_non_zero_a, mut count_non_zero_b) = (0usize, 0usize);
-
-    /* Count nun zeros on lookup constrains */
-    if let Some(lookup) = &lookup {
-        /* We only need to count for A, since B and C are all zeros */
-        let count_num_zero_lookup_a = lookup
-            .constraints
-            .iter()
-            .fold(0, |acc, constraint| acc + constraint.a.len());
-        count_non_zero_a += count_num_zero_lookup_a;
-    }
-
-    r1cs.0.iter().try_for_each(|constraint| -> Result<_> {
-        let mut a = func_convert_lc(&constraint.a)?;
-        let mut b = func_convert_lc(&constraint.b)?;
-        let c = func_convert_lc(&constraint.c)?;
-
-        let mut len_a = constraint.a.len();
-        let mut len_b = constraint.b.len();
-
-        if (len_a < len_b && count_non_zero_a < count_non_zero_b)
-            || (len_a > len_b && count_non_zero_a > count_non_zero_b)
-        {
-            /* Swap a and b to make non zeros values of A and B more balance. */
-            let t = b;
-            b = a;
-            a = t;
-
-            let t = len_b;
-            len_b = len_a;
-            len_a = t;
-        }
+    /* Swap a and b of some constraints to make non zeros values of A and B more balance. */
+    converted
+        .iter()
+        .zip(swaps)
+        .zip(&constant_rows)
+        .filter(|(_, constant)| !**constant)
+        .try_for_each(|(((a, b, c), swap), _)| -> Result<_> {
+            let (a, b) = if *swap { (b, a) } else { (a, b) };
 
-        count_non_zero_a += len_a;
-        count_non_zero_b += len_b;
+            let a = func_convert_lc(a)?;
+            let b = func_convert_lc(b)?;
+            let c = func_convert_lc(c)?;
 
-        Env::enforce(|| (a, b, c));
-        Ok(())
-    })?;
+            Env::enforce(|| (a, b, c));
+            Ok(())
+        })?;
 
     if let Some(lookup) = lookup {
         let mut table = LookupTable::default();
@@ -126,13 +330,12 @@ pub(crate) fn construct_r1cs_from_file(
         Env::add_lookup_table(table);
 
         let table_index = 0; /* Currently we only have one table */
-        lookup
-            .constraints
+        converted_lookup
             .iter()
-            .try_for_each(|constraint| -> Result<_> {
-                let a = func_convert_lc(&constraint.a)?;
-                let b = func_convert_lc(&constraint.b)?;
-                let c = func_convert_lc(&constraint.c)?;
+            .try_for_each(|(a, b, c)| -> Result<_> {
+                let a = func_convert_lc(a)?;
+                let b = func_convert_lc(b)?;
+                let c = func_convert_lc(c)?;
 
                 Env::enforce_lookup(|| (a, b, c, table_index));
                 Ok(())
@@ -141,3 +344,206 @@ pub(crate) fn construct_r1cs_from_file(
 
     Ok(())
 }
+
+/// Maps `f` over `items` and their indices with rayon, unless the caller is itself a rayon worker.
+///
+/// A worker waiting for a parallel iterator runs other tasks of its pool in the meantime. In the
+/// witness generation pool of `api::generate_assignments`, such a task is another circuit
+/// synthesis: it would exceed the bound on concurrent syntheses, and reset the thread-local
+/// circuit of the synthesis which is waiting. The workers already run one synthesis each, so the
+/// items are mapped sequentially there.
+pub(crate) fn try_map<T: Sync, R: Send>(
+    items: &[T],
+    f: impl Fn(usize, &T) -> Result<R> + Sync + Send,
+) -> Result<Vec<R>> {
+    if rayon::current_thread_index().is_some() {
+        items
+            .iter()
+            .enumerate()
+            .map(|(i, item)| f(i, item))
+            .collect()
+    } else {
+        items
+            .par_iter()
+            .enumerate()
+            .map(|(i, item)| f(i, item))
+            .collect()
+    }
+}
+
+/// Converts the coefficients of a linear combination into the field, and checks that it only
+/// refers to the `num_variables` variables of the assignment.
+///
+/// Terms are sorted by variable index, so the resulting linear combination does not depend on
+/// the iteration order of the `HashMap`. Terms with a zero coefficient are dropped.
+fn convert_lc(lc: &HashMap<usize, BigInt>, num_variables: usize) -> Result<Terms> {
+    let mut terms = lc
+        .iter()
+        .map(|(index, coeff)| {
+            ensure!(
+                *index < num_variables,
+                "variable {index} is out of range ({num_variables} variables)"
+            );
+            Ok((*index, EF::try_from(coeff)?))
+        })
+        .filter(|term| !matches!(term, Ok((_, coeff)) if coeff.is_zero()))
+        .collect::<Result<Vec<_>>>()?;
+    terms.sort_unstable_by_key(|(index, _)| *index);
+    Ok(terms)
+}
+
+/// The value of `terms` if they only have terms of the variable 0, the constant 1.
+fn constant_value(terms: &Terms) -> Option<EF> {
+    terms.iter().all(|(index, _)| *index == 0).then(|| {
+        terms
+            .iter()
+            .fold(EF::zero(), |sum, (_, coeff)| sum + *coeff)
+    })
+}
+
+fn convert_constraint(
+    constraint: &deserialize::Constraint,
+    num_variables: usize,
+) -> Result<(Terms, Terms, Terms)> {
+    Ok((
+        convert_lc(&constraint.a, num_variables)?,
+        convert_lc(&constraint.b, num_variables)?,
+        convert_lc(&constraint.c, num_variables)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use snarkvm_circuit::Circuit;
+
+    use super::*;
+    use crate::r1cs_provider::fixtures;
+
+    fn build(assignment: &str) -> Result<BuildOutput> {
+        Circuit::reset();
+        construct_r1cs_from_file::<Circuit>(
+            fixtures::path("r1cs"),
+            fixtures::path(assignment),
+            Some(fixtures::path("lookup")),
+            Some(fixtures::path("labels")),
+            &BuildOptions::default(),
+            None,
+        )
+    }
+
+    #[test]
+    fn test_construct_r1cs() -> Result<()> {
+        let output = build(fixtures::SATISFIED)?;
+        assert_eq!(output.plan.stats.num_constraints, 3);
+        assert_eq!(output.labels.unwrap().constraints, vec![0, 0, 1]);
+        assert!(Circuit::is_satisfied());
+        let assignment = Circuit::eject_assignment_and_reset();
+        assert_eq!(assignment.num_constraints(), 3);
+        assert_eq!(assignment.num_lookup_constraints(), 1);
+
+        build(fixtures::BAD_CONSTRAINT)?;
+        assert!(!Circuit::is_satisfied());
+        Circuit::reset();
+        Ok(())
+    }
+
+    #[test]
+    fn test_reuse_plan() -> Result<()> {
+        let output = build(fixtures::SATISFIED)?;
+        let build = |r1cs, assignment, lookup| {
+            Circuit::reset();
+            let result = construct_r1cs_from_parsed::<Circuit>(
+                r1cs,
+                assignment,
+                lookup,
+                None,
+                &BuildOptions::default(),
+                Some(output.plan.clone()),
+            );
+            Circuit::reset();
+            result
+        };
+
+        let (r1cs, assignment, lookup) = fixtures::parse(fixtures::BAD_LOOKUP)?;
+        let reused = build(r1cs, assignment, lookup)?;
+        assert!(Arc::ptr_eq(&reused.plan, &output.plan));
+
+        let (mut r1cs, assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        r1cs.0.pop();
+        let error = build(r1cs, assignment, lookup).unwrap_err();
+        assert!(
+            error.to_string().contains("does not match its plan"),
+            "{error}"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_invalid_inputs() -> Result<()> {
+        let build = |r1cs, assignment, lookup| {
+            Circuit::reset();
+            let result = construct_r1cs_from_parsed::<Circuit>(
+                r1cs,
+                assignment,
+                lookup,
+                None,
+                &BuildOptions::default(),
+                None,
+            );
+            Circuit::reset();
+            result
+        };
+        let not_in_field = BigInt([u64::MAX; 4]);
+
+        let (mut r1cs, assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        r1cs.0[1].b.insert(6, BigInt([1, 0, 0, 0]));
+        let error = build(r1cs, assignment, lookup).unwrap_err();
+        assert!(error.to_string().contains("out of range"), "{error}");
+
+        let (mut r1cs, assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        r1cs.0[0].c.insert(3, not_in_field.clone());
+        let error = build(r1cs, assignment, lookup).unwrap_err();
+        assert!(error.to_string().contains("not an element"), "{error}");
+
+        let (r1cs, mut assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        assignment.variables[4] = not_in_field;
+        assert!(build(r1cs, assignment, lookup).is_err());
+
+        let (r1cs, assignment, mut lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        lookup.as_mut().unwrap().constraints[0]
+            .a
+            .insert(usize::MAX, BigInt([1, 0, 0, 0]));
+        assert!(build(r1cs, assignment, lookup).is_err());
+
+        let (r1cs, mut assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        assignment.num_public_inputs = 7;
+        assert!(build(r1cs, assignment, lookup).is_err());
+
+        /* a constraint between constants is checked without snarkVM, which would panic */
+        let one = || HashMap::from([(0, BigInt([1, 0, 0, 0]))]);
+        let (mut r1cs, assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        r1cs.0.push(deserialize::Constraint {
+            a: one(),
+            b: one(),
+            c: HashMap::from([(0, BigInt([2, 0, 0, 0]))]),
+        });
+        let error = build(r1cs, assignment, lookup).unwrap_err();
+        assert!(
+            error.to_string().contains("constraint 3 does not hold"),
+            "{error}"
+        );
+        let (mut r1cs, assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        r1cs.0.push(deserialize::Constraint {
+            a: one(),
+            b: one(),
+            c: one(),
+        });
+        let stats = &build(r1cs, assignment, lookup)?.plan.stats;
+        assert_eq!((stats.num_constraints, stats.num_constant_rows), (3, 1));
+
+        let truncated = std::fs::read(fixtures::path("r1cs"))?;
+        let truncated = &truncated[..truncated.len() - 1];
+        assert!(deserialize::decode::<deserialize::R1CS>(truncated, "r1cs").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,167 @@
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, ensure, Result};
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use snarkvm_circuit_environment::prelude::snarkvm_fields::{One as _, Zero as _};
+use snarkvm_circuit_environment::prelude::PrimeField;
+
+use super::builder::{self, EF};
+use super::deserialize::{Assignment, BigInt, Constraint, Labels, Lookup, R1CS};
+
+/// The first constraint which is not satisfied by an assignment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Violation {
+    /// Index into `R1CS::0`.
+    Constraint(usize),
+    /// Index into `Lookup::constraints`.
+    Lookup(usize),
+}
+
+impl Violation {
+    /// The label of the violated constraint, if the circuit has labels.
+    pub fn label<'a>(&self, labels: &'a Labels) -> Option<&'a str> {
+        match self {
+            Violation::Constraint(index) => labels.constraint(*index),
+            Violation::Lookup(index) => labels.lookup_constraint(*index),
+        }
+    }
+}
+
+/// Checks an assignment against the imported R1CS and lookup constraints, without building the
+/// circuit in `snarkvm_circuit`.
+///
+/// Returns an error if the files are inconsistent (e.g. a variable index out of range), and the
+/// first violated constraint if the assignment does not satisfy them.
+pub fn check(
+    r1cs: &R1CS,
+    assignment: &Assignment,
+    lookup: Option<&Lookup>,
+) -> Result<Option<Violation>> {
+    let values = values(assignment)?;
+
+    let satisfied = r1cs
+        .0
+        .par_iter()
+        .map(|constraint| -> Result<_> {
+            let (a, b, c) = evaluate_constraint(&values, constraint)?;
+            Ok(a * b == c)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if let Some(index) = satisfied.iter().position(|satisfied| !satisfied) {
+        return Ok(Some(Violation::Constraint(index)));
+    }
+
+    if let Some(lookup) = lookup {
+        let table = lookup.table.0.iter().copied().collect::<HashSet<_>>();
+        let satisfied = lookup
+            .constraints
+            .par_iter()
+            .map(|constraint| -> Result<_> {
+                let (a, b, c) = evaluate_constraint(&values, constraint)?;
+                Ok(match (to_u32(&a), to_u32(&b), to_u32(&c)) {
+                    (Some(a), Some(b), Some(c)) => table.contains(&[a, b, c]),
+                    _ => false,
+                })
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if let Some(index) = satisfied.iter().position(|satisfied| !satisfied) {
+            return Ok(Some(Violation::Lookup(index)));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Like [`check`], but fails with an error naming the violated constraint and its label.
+pub fn ensure_satisfied(
+    r1cs: &R1CS,
+    assignment: &Assignment,
+    lookup: Option<&Lookup>,
+    labels: Option<&Labels>,
+) -> Result<()> {
+    match check(r1cs, assignment, lookup)? {
+        None => Ok(()),
+        Some(violation) => {
+            let label = labels
+                .and_then(|labels| violation.label(labels))
+                .unwrap_or("unlabeled");
+            bail!("{violation:?} is not satisfied (in {label})")
+        }
+    }
+}
+
+/// The values of the variables, with the first element replaced by `1` as done by the builder.
+pub(crate) fn values(assignment: &Assignment) -> Result<Vec<EF>> {
+    /* also called by the builder during the synthesis, see `builder::try_map` */
+    builder::try_map(&assignment.variables, |id, variable| {
+        if id == 0 {
+            Ok(EF::one())
+        } else {
+            EF::try_from(variable)
+        }
+    })
+}
+
+pub(crate) fn evaluate(values: &[EF], lc: &HashMap<usize, BigInt>) -> Result<EF> {
+    let mut sum = EF::zero();
+    for (index, coeff) in lc {
+        ensure!(
+            *index < values.len(),
+            "variable {index} is out of range ({} variables)",
+            values.len()
+        );
+        sum += values[*index] * EF::try_from(coeff)?;
+    }
+    Ok(sum)
+}
+
+fn evaluate_constraint(values: &[EF], constraint: &Constraint) -> Result<(EF, EF, EF)> {
+    Ok((
+        evaluate(values, &constraint.a)?,
+        evaluate(values, &constraint.b)?,
+        evaluate(values, &constraint.c)?,
+    ))
+}
+
+fn to_u32(value: &EF) -> Option<u32> {
+    let limbs = value.to_bigint().0;
+    if limbs[1..].iter().all(|limb| *limb == 0) {
+        u32::try_from(limbs[0]).ok()
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::r1cs_provider::fixtures;
+
+    #[test]
+    fn test_check() -> Result<()> {
+        let (r1cs, assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        assert_eq!(check(&r1cs, &assignment, lookup.as_ref())?, None);
+
+        let (r1cs, assignment, lookup) = fixtures::parse(fixtures::BAD_CONSTRAINT)?;
+        let violation = check(&r1cs, &assignment, lookup.as_ref())?;
+        assert_eq!(violation, Some(Violation::Constraint(1)));
+        assert_eq!(violation.unwrap().label(&fixtures::labels()?), Some("cube"));
+
+        let (r1cs, assignment, lookup) = fixtures::parse(fixtures::BAD_LOOKUP)?;
+        assert_eq!(
+            check(&r1cs, &assignment, lookup.as_ref())?,
+            Some(Violation::Lookup(0))
+        );
+        let error = ensure_satisfied(
+            &r1cs,
+            &assignment,
+            lookup.as_ref(),
+            Some(&fixtures::labels()?),
+        )
+        .unwrap_err();
+        assert!(error.to_string().contains("range"));
+        /* without the lookup constraints, the R1CS alone is satisfied */
+        assert_eq!(check(&r1cs, &assignment, None)?, None);
+        Ok(())
+    }
+}
@@ -1,13 +1,16 @@
 use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufReader, Read};
 use std::path::Path;
-use std::{fs::File, io::BufReader};
 
-use aleo_std_profiler::{end_timer, start_timer};
-use anyhow::{Context as _, Result};
-use scopeguard::defer;
-use serde::{Deserialize, Serialize};
+use anyhow::{ensure, Context as _, Result};
+use hap_verifier::Encoding;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::{field, info_span};
 
-#[derive(Debug, Serialize, Deserialize)]
+use crate::report::Phase;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BigInt(pub [u64; 4]);
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -24,6 +27,9 @@ pub struct Constraint {
 pub struct Assignment {
     pub variables: Vec<BigInt>, /* values in the witness, the first element "1" is also included */
     pub num_public_inputs: usize, /* number of public, include the first element "1" */
+    /// Layout of the public inputs, `bytes` if the generator does not write it.
+    #[serde(default)]
+    pub public_encoding: Encoding,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,31 +41,94 @@ pub struct Lookup {
     pub constraints: Vec<Constraint>, /* Additional constraints which are lookup constraints */
 }
 
+/// Optional provenance of the constraints, e.g. `keccak`, `ecdsa/scalar_mul` or `field/mul`.
+///
+/// Every constraint refers to an entry of `namespaces`, in the order of the R1CS and lookup
+/// constraints.
+///
+/// `labels.cbor` is optional and gnark-circuit-gen does not write it: the generator would have to
+/// record the namespace of the gadget for every constraint it emits. It is written by hand, as for
+/// the fixtures in `tests/fixtures/cube`, or by a separate tool, and passed to
+/// `hap inspect --labels` and `hap check --labels`; the builder also reads it when it is next to
+/// the files of the generator.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Labels {
+    pub namespaces: Vec<String>,
+    pub constraints: Vec<u32>, /* index into namespaces for each constraint of the R1CS */
+    pub lookup_constraints: Vec<u32>, /* index into namespaces for each lookup constraint */
+}
+
+impl Labels {
+    pub fn constraint(&self, index: usize) -> Option<&str> {
+        self.namespace(self.constraints.get(index)?)
+    }
+
+    pub fn lookup_constraint(&self, index: usize) -> Option<&str> {
+        self.namespace(self.lookup_constraints.get(index)?)
+    }
+
+    fn namespace(&self, index: &u32) -> Option<&str> {
+        self.namespaces.get(*index as usize).map(String::as_str)
+    }
+
+    /// Keeps the labels of the selected constraints, e.g. after optimizing the R1CS.
+    pub fn select(&self, constraints: &[usize], lookup_constraints: &[usize]) -> Self {
+        Self {
+            namespaces: self.namespaces.clone(),
+            constraints: constraints.iter().map(|i| self.constraints[*i]).collect(),
+            lookup_constraints: lookup_constraints
+                .iter()
+                .map(|i| self.lookup_constraints[*i])
+                .collect(),
+        }
+    }
+
+    /// Checks that there is exactly one valid label per constraint.
+    pub fn validate(&self, num_constraints: usize, num_lookup_constraints: usize) -> Result<()> {
+        ensure!(
+            self.constraints.len() == num_constraints,
+            "{} labels for {num_constraints} constraints",
+            self.constraints.len()
+        );
+        ensure!(
+            self.lookup_constraints.len() == num_lookup_constraints,
+            "{} labels for {num_lookup_constraints} lookup constraints",
+            self.lookup_constraints.len()
+        );
+        ensure!(
+            self.constraints
+                .iter()
+                .chain(&self.lookup_constraints)
+                .all(|index| (*index as usize) < self.namespaces.len()),
+            "label refers to an unknown namespace"
+        );
+        Ok(())
+    }
+}
+
 pub fn parse_file(
     r1cs_file: impl AsRef<Path>,
     assignment_file: impl AsRef<Path>,
     lookup_file: Option<impl AsRef<Path>>,
 ) -> Result<(R1CS, Assignment, Option<Lookup>)> {
-    let parse_time = start_timer!(|| "deserialize::parse_file()");
-    defer! {
-        end_timer!(parse_time);
-    }
+    let span = info_span!(
+        "parse_file",
+        num_constraints = field::Empty,
+        num_lookup_constraints = field::Empty
+    );
+    let _span = span.enter();
+    let _phase = Phase::Parse.start();
 
-    let file = File::open(r1cs_file)?;
-    let reader = BufReader::new(file);
-    let r1cs: R1CS = serde_cbor::from_reader(reader).context("error while parsing r1cs file")?;
+    let r1cs: R1CS = decode(BufReader::new(File::open(r1cs_file)?), "r1cs")?;
+    span.record("num_constraints", r1cs.0.len());
 
-    let file = File::open(assignment_file)?;
-    let reader = BufReader::new(file);
     let assignment: Assignment =
-        serde_cbor::from_reader(reader).context("error while parsing assignment file")?;
+        decode(BufReader::new(File::open(assignment_file)?), "assignment")?;
 
     let lookup = match lookup_file {
         Some(lookup_file) => {
-            let file = File::open(lookup_file)?;
-            let reader = BufReader::new(file);
-            let lookup: Lookup =
-                serde_cbor::from_reader(reader).context("error while parsing lookup file")?;
+            let lookup: Lookup = decode(BufReader::new(File::open(lookup_file)?), "lookup")?;
+            span.record("num_lookup_constraints", lookup.constraints.len());
             Some(lookup)
         }
         _ => None,
@@ -68,27 +137,45 @@ pub fn parse_file(
     Ok((r1cs, assignment, lookup))
 }
 
+pub fn parse_labels(labels_file: impl AsRef<Path>) -> Result<Labels> {
+    decode(BufReader::new(File::open(labels_file)?), "labels")
+}
+
+/// Decodes one of the exported files, `what` names it in the error.
+///
+/// The files are not trusted: malformed CBOR is an error, and the decoder caps the capacity it
+/// preallocates from the lengths it reads, so that memory only grows with the actual input.
+/// The values are checked when the circuit is built, see [`super::builder`].
+pub fn decode<T: DeserializeOwned>(reader: impl Read, what: &str) -> Result<T> {
+    serde_cbor::from_reader(reader).with_context(|| format!("error while parsing {what} file"))
+}
+
 #[cfg(test)]
 mod tests {
-
     use super::*;
+    use crate::r1cs_provider::fixtures;
 
     #[test]
     fn test_parse() -> Result<()> {
-        let file = File::open("../gnark-plonky2-verifier/output/r1cs.cbor")?;
-        let reader = BufReader::new(file);
-        let _r1cs: R1CS = serde_cbor::from_reader(reader)?;
-        // println!("{:#?}", r1cs);
-
-        let file = File::open("../gnark-plonky2-verifier/output/assignment.cbor")?;
-        let reader = BufReader::new(file);
-        let _assignment: Assignment = serde_cbor::from_reader(reader)?;
-        // println!("{:#?}", assignment);
-
-        let file = File::open("../gnark-plonky2-verifier/output/lookup.cbor")?;
-        let reader = BufReader::new(file);
-        let _lookup: Lookup = serde_cbor::from_reader(reader)?;
-        // println!("{:#?}", lookup);
+        let (r1cs, assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        assert_eq!(r1cs.0.len(), 3);
+        assert_eq!(r1cs.0[2].a.len(), 3);
+        assert_eq!(r1cs.0[2].a[&0].0, [5, 0, 0, 0]);
+        assert_eq!(assignment.variables.len(), 6);
+        assert_eq!(assignment.variables[2].0, [35, 0, 0, 0]);
+        assert_eq!(assignment.num_public_inputs, 3);
+        assert_eq!(assignment.public_encoding, Encoding::Bytes);
+
+        let lookup = lookup.unwrap();
+        assert_eq!(lookup.table.0.len(), 16);
+        assert_eq!(lookup.table.0[15], [15, 0, 0]);
+        assert_eq!(lookup.constraints.len(), 1);
+        assert!(lookup.constraints[0].b.is_empty());
+
+        let labels = fixtures::labels()?;
+        labels.validate(r1cs.0.len(), lookup.constraints.len())?;
+        assert_eq!(labels.constraint(1), Some("cube"));
+        assert_eq!(labels.lookup_constraint(0), Some("range"));
         Ok(())
     }
 }
@@ -0,0 +1,38 @@
+//! The small circuit checked in at `tests/fixtures/cube`, for the tests which cannot run
+//! gnark-circuit-gen.
+//!
+//! The variables are `[1, x, out | x², x³, r]`, with `x` and `out` public:
+//!
+//! - `cube`: `x * x = x²` and `x² * x = x³`,
+//! - `sum`: `(x³ + x + 5) * 1 = out`,
+//! - `range`: the lookup constraint `r + x` in the table `[v, 0, 0]` for `v < 16`.
+//!
+//! `assignment.cbor` satisfies it with `x = 3`, `out = 35` and `r = 5`.
+//! `assignment_bad_constraint.cbor` has `x³ = 28`, which violates the second constraint, and
+//! `assignment_bad_lookup.cbor` has `r = 13`, which violates the lookup constraint only.
+
+use std::path::PathBuf;
+
+use anyhow::Result;
+
+use super::deserialize::{self, Assignment, Labels, Lookup, R1CS};
+
+pub(crate) const SATISFIED: &str = "assignment";
+pub(crate) const BAD_CONSTRAINT: &str = "assignment_bad_constraint";
+pub(crate) const BAD_LOOKUP: &str = "assignment_bad_lookup";
+
+/// The path of `<name>.cbor`.
+pub(crate) fn path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/cube")
+        .join(format!("{name}.cbor"))
+}
+
+/// The circuit with one of the assignments.
+pub(crate) fn parse(assignment: &str) -> Result<(R1CS, Assignment, Option<Lookup>)> {
+    deserialize::parse_file(path("r1cs"), path(assignment), Some(path("lookup")))
+}
+
+pub(crate) fn labels() -> Result<Labels> {
+    deserialize::parse_labels(path("labels"))
+}
@@ -1,58 +1,119 @@
-use aleo_std_profiler::{end_timer, start_timer};
 use anyhow::bail;
 use anyhow::Context;
 use anyhow::Result;
 use log::debug;
-use scopeguard::defer;
+use num_bigint::BigUint;
+use sha3::{Digest, Keccak256};
+use snarkvm_circuit::Environment;
+use snarkvm_curves::bls12_377::Fr;
 use std::env;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 use std::process::Stdio;
+use std::sync::Arc;
 use tempfile::Builder;
+use tracing::info_span;
 
+use crate::input::SignatureInput;
+use crate::report::Phase;
 use crate::TestCase;
 
-pub fn build_r1cs(test_case: TestCase) -> Result<()> {
-    let build_time = start_timer!(|| "build_r1cs()");
-    defer! {
-        end_timer!(build_time);
-    }
-
-    let _tmp_dir = Builder::new().prefix("hap").tempdir()?;
-    let tmp_dir = _tmp_dir.path();
+use super::builder::{BuildOptions, BuildOutput, CircuitPlan};
 
-    // go run your_program.go -pk_x "123456789012345678901234567890" -pk_y "987654321098765432109876543210" -sig_r "112233445566778899001122334455" -sig_s "998877665544332211009988776655" -hash "123123123123123123123123123123"
-    let output_dir = tmp_dir.join("output");
-    std::fs::create_dir_all(&output_dir)
-        .with_context(|| format!("Failed to create output dir at: {output_dir:?}"))?;
-    run_external_process(
-        Command::new(env::current_dir()?.join("../gnark-circuit-gen/main"))
-            .args(&[match test_case {
-                TestCase::Test1 => "test1",
-                TestCase::Test2 => "test2",
-                TestCase::Test3 => "test3",
-            }])
-            .current_dir(&tmp_dir),
+/// Builds the circuit of `test_case` in the circuit environment `Env` of a network. With a
+/// signature, the generator computes the assignment for it, otherwise for a random signature.
+///
+/// `plan` is the one of a previous build of the same test case with the same options, if any.
+pub fn build_r1cs<Env: Environment<BaseField = Fr>>(
+    test_case: TestCase,
+    signature: Option<&SignatureInput>,
+    options: &BuildOptions,
+    plan: Option<Arc<CircuitPlan>>,
+) -> Result<BuildOutput> {
+    let _span = info_span!(
+        "build_r1cs",
+        test_case = test_case.name(),
+        encoding = options.encoding.name()
     )
-    .context("Failed to execute gnark-circuit-gen")?;
+    .entered();
+
+    let tmp_dir = Builder::new().prefix("hap").tempdir()?;
+    let output_dir = generate(test_case, signature, tmp_dir.path())?;
+
+    /* Labels are optional, the generator does not write them, see `deserialize::Labels` */
+    let labels_file = Some(output_dir.join("labels.cbor")).filter(|file| file.exists());
 
-    let ret = super::builder::construct_r1cs_from_file(
+    super::builder::construct_r1cs_from_file::<Env>(
         output_dir.join("r1cs.cbor"),
         output_dir.join("assignment.cbor"),
         Some(output_dir.join("lookup.cbor")),
-    );
-    ret
+        labels_file,
+        options,
+        plan,
+    )
+}
+
+/// Runs gnark-circuit-gen in `work_dir`, and returns the directory of the exported files:
+/// `r1cs.cbor`, `assignment.cbor` and `lookup.cbor`.
+///
+/// With a signature, the generator is run with the flags of its interface, all decimal integers:
+///
+/// ```text
+/// main -pk_x <x> -pk_y <y> -sig_r <r> -sig_s <s> -hash <keccak256(msg)> test1
+/// ```
+///
+/// Fails if the generator fails, e.g. when it cannot solve the circuit for `signature`, with its
+/// standard error in the message. The prover checks the exported public inputs against the
+/// [`Encoding::Bytes`] layout of the signature, the builder repacks them for the other encodings.
+pub fn generate(
+    test_case: TestCase,
+    signature: Option<&SignatureInput>,
+    work_dir: &Path,
+) -> Result<PathBuf> {
+    let output_dir = work_dir.join("output");
+    std::fs::create_dir_all(&output_dir)
+        .with_context(|| format!("Failed to create output dir at: {output_dir:?}"))?;
+    let mut cmd = Command::new(env::current_dir()?.join("../gnark-circuit-gen/main"));
+    cmd.current_dir(work_dir);
+    /* flags go before the test case, the go flag package stops at the first argument */
+    if let Some(signature) = signature {
+        signature.validate()?;
+        let (pk_x, pk_y) = signature.public_key.split_at(32);
+        let (sig_r, sig_s) = signature.signature.split_at(32);
+        let hash = Keccak256::digest(&signature.message);
+        for (flag, value) in [
+            ("-pk_x", pk_x),
+            ("-pk_y", pk_y),
+            ("-sig_r", sig_r),
+            ("-sig_s", sig_s),
+            ("-hash", hash.as_slice()),
+        ] {
+            cmd.arg(flag).arg(BigUint::from_bytes_be(value).to_string());
+        }
+    }
+    cmd.arg(test_case.name());
+    info_span!("generator", test_case = test_case.name())
+        .in_scope(|| run_external_process(&mut cmd))
+        .context("Failed to execute gnark-circuit-gen")?;
+    Ok(output_dir)
 }
 
 fn run_external_process(cmd: &mut Command) -> Result<()> {
     debug!("run cmd: {:?}", cmd);
+    let _phase = Phase::Generator.start();
     cmd.stdout(Stdio::null());
     cmd.stdin(Stdio::null());
-    cmd.stderr(Stdio::null());
-    let status = cmd
-        .status()
-        .with_context(|| format!("Failed to execute{:?}", cmd))?;
-    if !status.success() {
-        bail!("Run process {:?} exited: {}", cmd, status);
+    cmd.stderr(Stdio::piped());
+    let output = cmd
+        .output()
+        .with_context(|| format!("Failed to execute {:?}", cmd))?;
+    if !output.status.success() {
+        bail!(
+            "Run process {:?} exited: {}\n{}",
+            cmd,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
     }
     Ok(())
 }
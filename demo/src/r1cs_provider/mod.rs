@@ -1,3 +1,10 @@
+pub mod balance;
 pub mod deserialize;
 pub mod builder;
+pub mod checker;
+#[cfg(test)]
+pub(crate) mod fixtures;
 pub mod gnark;
+pub mod optimize;
+pub mod packing;
+pub mod stats;
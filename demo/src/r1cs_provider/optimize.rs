@@ -0,0 +1,574 @@
+//! Optimization passes over the imported R1CS.
+//!
+//! The circuits exported by gnark contain constraints which are linear (`a` or `b` is a
+//! constant), duplicate and trivially satisfied rows. This module removes them before the
+//! circuit is built:
+//!
+//! 1. A linear constraint `l(x) = 0` is solved for one of its private variables `x`, and `x` is
+//!    substituted in every other constraint (R1CS and lookup) where it occurs. The row is then
+//!    removed. Since `x` no longer occurs anywhere, any assignment of the remaining variables
+//!    which satisfies the optimized system is extended to an assignment of the original system
+//!    by computing `x` from its [`Substitution`]. Public variables are never substituted.
+//! 2. Constraints which hold for every assignment (e.g. `0 * b = 0`) are removed. A constraint
+//!    which holds for no assignment (e.g. `1 * 1 = 2`) is an error, the circuit cannot be proved.
+//! 3. Duplicate constraints (up to swapping `a` and `b`) are removed.
+//!
+//! Linear combinations are kept in a canonical form, so constant terms produced by the
+//! substitutions are merged with the existing ones and zero terms are dropped.
+//!
+//! [`check_equivalence`] compares the original and the optimized system with the satisfaction
+//! checker on sample witnesses.
+//!
+//! The passes are only run by `hap inspect --optimize`: the keys and the proofs of `hap compile`
+//! and `hap prove` are always built from the circuit as exported.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+
+use anyhow::{bail, ensure, Result};
+use rand::Rng;
+use serde::Serialize;
+use snarkvm_circuit_environment::prelude::snarkvm_fields::{Field as _, One as _, Zero as _};
+use tracing::info_span;
+
+use super::builder::EF;
+use super::checker;
+use super::deserialize::{Assignment, BigInt, Constraint, Lookup, LookupTable, R1CS};
+
+/// A linear combination in canonical form: sorted by variable, without zero coefficients.
+type Lc = BTreeMap<usize, EF>;
+
+/// Index of the constant variable `1`.
+const ONE: usize = 0;
+
+/// A variable eliminated by the optimizer, together with its value as a linear combination of
+/// the remaining variables.
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    pub variable: usize,
+    pub value: HashMap<usize, BigInt>,
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct OptimizeReport {
+    pub num_constraints_before: usize,
+    pub num_constraints_after: usize,
+    pub num_substituted: usize,
+    pub num_trivial: usize,
+    pub num_duplicates: usize,
+    pub num_non_zero_before: usize,
+    pub num_non_zero_after: usize,
+}
+
+#[derive(Debug)]
+pub struct Optimized {
+    pub r1cs: R1CS,
+    pub lookup: Option<Lookup>,
+    pub substitutions: Vec<Substitution>,
+    /// For every constraint of `r1cs`, its index in the original R1CS.
+    pub kept_constraints: Vec<usize>,
+    /// For every constraint of `lookup`, its index in the original lookup constraints.
+    pub kept_lookups: Vec<usize>,
+    pub report: OptimizeReport,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Row {
+    Constraint(usize),
+    Lookup(usize),
+    Substitution(usize),
+}
+
+struct Rows {
+    constraints: Vec<Option<[Lc; 3]>>,
+    lookups: Vec<[Lc; 3]>,
+    substitutions: Vec<(usize, Lc)>,
+    /// For every variable, the rows in which it occurs.
+    occurrences: HashMap<usize, BTreeSet<Row>>,
+}
+
+impl Rows {
+    fn lcs_mut(&mut self, row: Row) -> &mut [Lc] {
+        match row {
+            Row::Constraint(i) => self.constraints[i]
+                .as_mut()
+                .expect("removed row has no occurrences"),
+            Row::Lookup(i) => &mut self.lookups[i],
+            Row::Substitution(i) => std::slice::from_mut(&mut self.substitutions[i].1),
+        }
+    }
+
+    fn track(&mut self, row: Row, lcs: &[Lc]) {
+        for variable in lcs.iter().flat_map(|lc| lc.keys()) {
+            self.occurrences.entry(*variable).or_default().insert(row);
+        }
+    }
+
+    fn untrack(&mut self, row: Row, lcs: &[Lc]) {
+        for variable in lcs.iter().flat_map(|lc| lc.keys()) {
+            if let Some(rows) = self.occurrences.get_mut(variable) {
+                rows.remove(&row);
+            }
+        }
+    }
+
+    /// Replaces `variable` by `value` in every row where it occurs.
+    fn substitute(&mut self, variable: usize, value: &Lc) {
+        let rows = self.occurrences.remove(&variable).unwrap_or_default();
+        for row in rows {
+            let before = self.lcs_mut(row).to_vec();
+            self.untrack(row, &before);
+            for lc in self.lcs_mut(row) {
+                if let Some(coeff) = lc.remove(&variable) {
+                    add_scaled(lc, value, &coeff);
+                }
+            }
+            let after = self.lcs_mut(row).to_vec();
+            self.track(row, &after);
+        }
+    }
+}
+
+/// Runs all optimization passes.
+///
+/// `num_public_inputs` is the number of public variables, including the constant `1`.
+pub fn optimize(
+    r1cs: &R1CS,
+    lookup: Option<&Lookup>,
+    num_public_inputs: usize,
+) -> Result<Optimized> {
+    let _span = info_span!("optimize", num_constraints = r1cs.0.len()).entered();
+
+    let mut report = OptimizeReport {
+        num_constraints_before: r1cs.0.len(),
+        num_non_zero_before: r1cs.0.iter().map(num_non_zero).sum(),
+        ..Default::default()
+    };
+
+    let mut rows = Rows {
+        constraints: r1cs
+            .0
+            .iter()
+            .map(|constraint| Ok(Some(to_lcs(constraint)?)))
+            .collect::<Result<_>>()?,
+        lookups: lookup
+            .map(|lookup| {
+                lookup
+                    .constraints
+                    .iter()
+                    .map(to_lcs)
+                    .collect::<Result<Vec<_>>>()
+            })
+            .transpose()?
+            .unwrap_or_default(),
+        substitutions: vec![],
+        occurrences: HashMap::new(),
+    };
+    for i in 0..rows.constraints.len() {
+        let lcs = rows.constraints[i].clone().unwrap();
+        rows.track(Row::Constraint(i), &lcs);
+    }
+    for i in 0..rows.lookups.len() {
+        let lcs = rows.lookups[i].clone();
+        rows.track(Row::Lookup(i), &lcs);
+    }
+
+    /* Pass 1: substitute linear constraints, in order */
+    for i in 0..rows.constraints.len() {
+        let Some(linear) = as_linear(rows.constraints[i].as_ref().unwrap()) else {
+            continue;
+        };
+        let row_non_zero = rows.constraints[i]
+            .as_ref()
+            .unwrap()
+            .iter()
+            .map(Lc::len)
+            .sum::<usize>();
+
+        /* Pick the private variable which is cheapest to eliminate */
+        let candidate = linear
+            .keys()
+            .filter(|variable| **variable >= num_public_inputs)
+            .map(|variable| (rows.occurrences[variable].len(), *variable))
+            .min();
+        let Some((occurrences, variable)) = candidate else {
+            continue;
+        };
+        /* Every other occurrence of `variable` is replaced by `linear.len() - 1` terms */
+        let added = (occurrences - 1) * (linear.len() - 1);
+        let removed = row_non_zero + (occurrences - 1);
+        if added > removed {
+            continue;
+        }
+
+        let lcs = rows.constraints[i].take().unwrap();
+        rows.untrack(Row::Constraint(i), &lcs);
+
+        let mut value = linear;
+        let coeff = value.remove(&variable).unwrap();
+        let scale = -coeff.inverse().expect("coefficients are non zero");
+        value.values_mut().for_each(|c| *c *= scale);
+
+        rows.substitute(variable, &value);
+        let index = rows.substitutions.len();
+        rows.track(Row::Substitution(index), std::slice::from_ref(&value));
+        rows.substitutions.push((variable, value));
+        report.num_substituted += 1;
+    }
+
+    /* Pass 2 and 3: drop trivially satisfied and duplicate constraints */
+    let mut seen = HashSet::new();
+    let mut constraints = vec![];
+    let mut kept_constraints = vec![];
+    for (i, row) in rows.constraints.into_iter().enumerate() {
+        let Some([a, b, c]) = row else {
+            continue;
+        };
+        match is_trivial(&a, &b, &c) {
+            Some(true) => {
+                report.num_trivial += 1;
+                continue;
+            }
+            Some(false) => bail!("constraint {i} does not hold for any assignment"),
+            None => {}
+        }
+        let (first, second) = if key(&a) <= key(&b) {
+            (&a, &b)
+        } else {
+            (&b, &a)
+        };
+        if !seen.insert((key(first), key(second), key(&c))) {
+            report.num_duplicates += 1;
+            continue;
+        }
+        constraints.push(from_lcs(&[a, b, c]));
+        kept_constraints.push(i);
+    }
+
+    let mut seen = HashSet::new();
+    let kept_lookups = (0..rows.lookups.len())
+        .filter(|i| {
+            let lcs = &rows.lookups[*i];
+            seen.insert((key(&lcs[0]), key(&lcs[1]), key(&lcs[2])))
+        })
+        .collect::<Vec<_>>();
+    let lookup = lookup.map(|lookup| Lookup {
+        table: LookupTable(lookup.table.0.clone()),
+        constraints: kept_lookups
+            .iter()
+            .map(|i| from_lcs(&rows.lookups[*i]))
+            .collect(),
+    });
+
+    let r1cs = R1CS(constraints);
+    report.num_constraints_after = r1cs.0.len();
+    report.num_non_zero_after = r1cs.0.iter().map(num_non_zero).sum();
+
+    let substitutions = rows
+        .substitutions
+        .iter()
+        .map(|(variable, value)| Substitution {
+            variable: *variable,
+            value: to_hash_map(value),
+        })
+        .collect();
+
+    Ok(Optimized {
+        r1cs,
+        lookup,
+        substitutions,
+        kept_constraints,
+        kept_lookups,
+        report,
+    })
+}
+
+/// Extends an assignment of the optimized system with the values of the substituted variables.
+pub fn complete_witness(
+    assignment: &Assignment,
+    substitutions: &[Substitution],
+) -> Result<Assignment> {
+    let values = checker::values(assignment)?;
+    let mut variables = assignment
+        .variables
+        .iter()
+        .map(|v| BigInt(v.0))
+        .collect::<Vec<_>>();
+    for substitution in substitutions {
+        ensure!(
+            substitution.variable < variables.len(),
+            "substituted variable {} is out of range",
+            substitution.variable
+        );
+        let value = checker::evaluate(&values, &substitution.value)?;
+        variables[substitution.variable] = BigInt::from(&value);
+    }
+    Ok(Assignment {
+        variables,
+        num_public_inputs: assignment.num_public_inputs,
+        public_encoding: assignment.public_encoding,
+    })
+}
+
+/// Checks on sample witnesses that the optimized system accepts exactly the assignments which the
+/// original system accepts, once completed with the substituted variables.
+///
+/// The samples are `assignment` itself and `num_samples` witnesses derived from it: one of the
+/// free variables (private and not substituted) is changed, then the substituted variables are
+/// computed again with [`complete_witness`]. A free variable which no constraint of the optimized
+/// system refers to, e.g. the input of a chain of linear constraints, can take any value, so when
+/// `assignment` is satisfying these samples are satisfying witnesses too. Every other sample
+/// changes such a variable, if there is one.
+///
+/// Returns the number of samples accepted by both systems.
+pub fn check_equivalence(
+    r1cs: &R1CS,
+    lookup: Option<&Lookup>,
+    optimized: &Optimized,
+    assignment: &Assignment,
+    num_samples: usize,
+    rng: &mut impl Rng,
+) -> Result<usize> {
+    let substituted = optimized
+        .substitutions
+        .iter()
+        .map(|substitution| substitution.variable)
+        .collect::<HashSet<_>>();
+    let lookup_constraints = optimized
+        .lookup
+        .as_ref()
+        .map_or(&[][..], |lookup| &lookup.constraints[..]);
+    let constrained = optimized
+        .r1cs
+        .0
+        .iter()
+        .chain(lookup_constraints)
+        .flat_map(|constraint| {
+            constraint
+                .a
+                .keys()
+                .chain(constraint.b.keys())
+                .chain(constraint.c.keys())
+        })
+        .copied()
+        .collect::<HashSet<_>>();
+    let (unconstrained, constrained): (Vec<_>, Vec<_>) = (assignment.num_public_inputs
+        ..assignment.variables.len())
+        .filter(|variable| !substituted.contains(variable))
+        .partition(|variable| !constrained.contains(variable));
+    ensure!(
+        !unconstrained.is_empty() || !constrained.is_empty() || num_samples == 0,
+        "there is no free variable to change"
+    );
+
+    let samples = std::iter::once(None).chain((0..num_samples).map(|i| {
+        let variables = if constrained.is_empty() || (i % 2 == 0 && !unconstrained.is_empty()) {
+            &unconstrained
+        } else {
+            &constrained
+        };
+        let variable = variables[rng.gen_range(0..variables.len())];
+        Some((variable, rng.gen::<u64>()))
+    }));
+
+    let mut num_accepted = 0;
+    for sample in samples {
+        let mut witness = Assignment {
+            variables: assignment.variables.iter().map(|v| BigInt(v.0)).collect(),
+            num_public_inputs: assignment.num_public_inputs,
+            public_encoding: assignment.public_encoding,
+        };
+        if let Some((variable, value)) = sample {
+            witness.variables[variable] = BigInt([value, 0, 0, 0]);
+        }
+        let witness = complete_witness(&witness, &optimized.substitutions)?;
+
+        let accepted_by_optimized =
+            checker::check(&optimized.r1cs, &witness, optimized.lookup.as_ref())?.is_none();
+        let accepted_by_original = checker::check(r1cs, &witness, lookup)?.is_none();
+
+        ensure!(
+            accepted_by_optimized == accepted_by_original,
+            "the optimized circuit is not equivalent to the original one on sample {sample:?}"
+        );
+        num_accepted += accepted_by_original as usize;
+    }
+    Ok(num_accepted)
+}
+
+fn num_non_zero(constraint: &Constraint) -> usize {
+    constraint.a.len() + constraint.b.len() + constraint.c.len()
+}
+
+fn to_lc(lc: &HashMap<usize, BigInt>) -> Result<Lc> {
+    let mut terms = Lc::new();
+    for (index, coeff) in lc {
+        let coeff = EF::try_from(coeff)?;
+        if !coeff.is_zero() {
+            terms.insert(*index, coeff);
+        }
+    }
+    Ok(terms)
+}
+
+fn to_lcs(constraint: &Constraint) -> Result<[Lc; 3]> {
+    Ok([
+        to_lc(&constraint.a)?,
+        to_lc(&constraint.b)?,
+        to_lc(&constraint.c)?,
+    ])
+}
+
+fn to_hash_map(lc: &Lc) -> HashMap<usize, BigInt> {
+    lc.iter()
+        .map(|(index, coeff)| (*index, BigInt::from(coeff)))
+        .collect()
+}
+
+fn from_lcs(lcs: &[Lc]) -> Constraint {
+    Constraint {
+        a: to_hash_map(&lcs[0]),
+        b: to_hash_map(&lcs[1]),
+        c: to_hash_map(&lcs[2]),
+    }
+}
+
+fn key(lc: &Lc) -> Vec<(usize, [u64; 4])> {
+    lc.iter()
+        .map(|(index, coeff)| (*index, BigInt::from(coeff).0))
+        .collect()
+}
+
+/// `lc += coeff * value`, keeping `lc` canonical.
+fn add_scaled(lc: &mut Lc, value: &Lc, coeff: &EF) {
+    for (index, c) in value {
+        let sum = lc.get(index).copied().unwrap_or_else(EF::zero) + *c * coeff;
+        if sum.is_zero() {
+            lc.remove(index);
+        } else {
+            lc.insert(*index, sum);
+        }
+    }
+}
+
+fn constant(lc: &Lc) -> Option<EF> {
+    match lc.len() {
+        0 => Some(EF::zero()),
+        1 => lc.get(&ONE).copied(),
+        _ => None,
+    }
+}
+
+/// If `a * b = c` is linear, returns `l` such that the constraint is `l = 0`.
+fn as_linear([a, b, c]: &[Lc; 3]) -> Option<Lc> {
+    let (k, other) = match (constant(a), constant(b)) {
+        (Some(k), _) => (k, b),
+        (_, Some(k)) => (k, a),
+        _ => return None,
+    };
+    let mut linear = Lc::new();
+    add_scaled(&mut linear, other, &k);
+    add_scaled(&mut linear, c, &-EF::one());
+    Some(linear)
+}
+
+/// Whether a constraint holds independently of the assignment: `Some(true)` if it is always
+/// satisfied, `Some(false)` if it never is, `None` otherwise.
+fn is_trivial(a: &Lc, b: &Lc, c: &Lc) -> Option<bool> {
+    if (a.is_empty() || b.is_empty()) && c.is_empty() {
+        return Some(true);
+    }
+    match (constant(a), constant(b), constant(c)) {
+        (Some(a), Some(b), Some(c)) => Some(a * b == c),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::OsRng;
+
+    use super::*;
+
+    fn lc(terms: &[(usize, u64)]) -> HashMap<usize, BigInt> {
+        terms
+            .iter()
+            .map(|(index, coeff)| (*index, BigInt([*coeff, 0, 0, 0])))
+            .collect()
+    }
+
+    fn constraint(a: &[(usize, u64)], b: &[(usize, u64)], c: &[(usize, u64)]) -> Constraint {
+        Constraint {
+            a: lc(a),
+            b: lc(b),
+            c: lc(c),
+        }
+    }
+
+    #[test]
+    fn test_optimize() -> Result<()> {
+        // variables: 1, out (public), x, y, z, w
+        // z = x + y, w = 2 * z, out = w * x
+        let r1cs = R1CS(vec![
+            constraint(&[(2, 1), (3, 1)], &[(0, 1)], &[(4, 1)]),
+            constraint(&[(0, 2)], &[(4, 1)], &[(5, 1)]),
+            constraint(&[(5, 1)], &[(2, 1)], &[(1, 1)]),
+            constraint(&[(2, 1)], &[(5, 1)], &[(1, 1)]),
+            constraint(&[], &[(3, 1)], &[]),
+        ]);
+        let assignment = Assignment {
+            variables: [1u64, 30, 3, 2, 5, 10]
+                .iter()
+                .map(|v| BigInt([*v, 0, 0, 0]))
+                .collect(),
+            num_public_inputs: 2,
+            public_encoding: Default::default(),
+        };
+        assert_eq!(checker::check(&r1cs, &assignment, None)?, None);
+
+        let optimized = optimize(&r1cs, None, assignment.num_public_inputs)?;
+        assert_eq!(optimized.report.num_substituted, 2);
+        assert_eq!(optimized.report.num_duplicates, 1);
+        assert_eq!(optimized.report.num_trivial, 1);
+        assert_eq!(optimized.r1cs.0.len(), 1);
+
+        check_equivalence(&r1cs, None, &optimized, &assignment, 16, &mut OsRng)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_check_equivalence() -> Result<()> {
+        // variables: 1, out (public), w, x, y, z
+        // out = w * w, x + 1 = y, y = z
+        let r1cs = R1CS(vec![
+            constraint(&[(2, 1)], &[(2, 1)], &[(1, 1)]),
+            constraint(&[(3, 1), (0, 1)], &[(0, 1)], &[(4, 1)]),
+            constraint(&[(4, 1)], &[(0, 1)], &[(5, 1)]),
+        ]);
+        let assignment = Assignment {
+            variables: [1u64, 9, 3, 4, 5, 5]
+                .iter()
+                .map(|v| BigInt([*v, 0, 0, 0]))
+                .collect(),
+            num_public_inputs: 2,
+            public_encoding: Default::default(),
+        };
+        let optimized = optimize(&r1cs, None, assignment.num_public_inputs)?;
+        assert_eq!(optimized.report.num_substituted, 2);
+        assert_eq!(optimized.r1cs.0.len(), 1);
+
+        /* `y` is free: the assignment itself and the 8 samples changing it are satisfying */
+        let num_accepted = check_equivalence(&r1cs, None, &optimized, &assignment, 16, &mut OsRng)?;
+        assert!(num_accepted >= 9, "{num_accepted} samples accepted");
+        Ok(())
+    }
+
+    #[test]
+    fn test_unsatisfiable_constant() {
+        let r1cs = R1CS(vec![
+            constraint(&[(2, 1)], &[(2, 1)], &[(1, 1)]),
+            constraint(&[(0, 1)], &[(0, 1)], &[(0, 2)]),
+        ]);
+        let error = optimize(&r1cs, None, 2).unwrap_err();
+        assert!(error.to_string().contains("constraint 1"), "{error}");
+    }
+}
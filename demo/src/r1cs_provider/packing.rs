@@ -0,0 +1,261 @@
+//! Packing of the public inputs of a circuit exported with [`Encoding::Bytes`] into the
+//! [`Encoding::Packed`] layout.
+//!
+//! gnark-circuit-gen only exports circuits with one public input per byte. [`repack`] makes
+//! these inputs private and adds the packed public inputs, each constrained to its linear
+//! combination of the bytes, see [`packed_from_bytes`]:
+//!
+//! ```text
+//! [1, packed inputs | bytes (former public inputs), private variables, bits of the bytes]
+//! ```
+//!
+//! A packed value only determines its bytes if they are below 256, so every byte but the length
+//! of the message is decomposed into 8 boolean variables. Without it, a prover could feed the
+//! signature circuit with other "bytes" packing to the same public inputs. The constraints of
+//! the export keep their indices, the packing constraints follow them.
+
+use std::collections::HashMap;
+
+use anyhow::{ensure, Result};
+use hap_verifier::encoding::packed_from_bytes;
+use hap_verifier::Encoding;
+use snarkvm_circuit_environment::prelude::snarkvm_fields::{One as _, Zero as _};
+use snarkvm_circuit_environment::prelude::PrimeField;
+use tracing::info_span;
+
+use super::builder::EF;
+use super::checker;
+use super::deserialize::{Assignment, BigInt, Constraint, Labels, Lookup, R1CS};
+
+/// Namespace of the packing constraints in the labels of the circuit.
+pub const NAMESPACE: &str = "public_inputs/packing";
+
+/// Index of the length of the message in [`Encoding::Bytes`].
+const LEN_INPUT: usize = 129;
+
+/// Repacks the circuit of `r1cs` and `lookup`, with public inputs in [`Encoding::Bytes`], into a
+/// circuit with public inputs in [`Encoding::Packed`], see the [module documentation](self).
+///
+/// The assignment is extended to the new variables, and the labels to the new constraints.
+pub fn repack(
+    r1cs: R1CS,
+    assignment: Assignment,
+    lookup: Option<Lookup>,
+    labels: Option<Labels>,
+) -> Result<(R1CS, Assignment, Option<Lookup>, Option<Labels>)> {
+    ensure!(
+        assignment.public_encoding == Encoding::Bytes,
+        "the public inputs are encoded as {:?}, expected {:?}",
+        assignment.public_encoding,
+        Encoding::Bytes
+    );
+    let num_public = assignment.num_public_inputs;
+    ensure!(
+        num_public >= Encoding::Bytes.num_public_inputs(0),
+        "{num_public} public inputs are too few for a signature"
+    );
+    let max_len = num_public - Encoding::Bytes.num_public_inputs(0);
+    let _span = info_span!("repack", max_len).entered();
+    let num_variables = assignment.variables.len();
+    ensure!(
+        num_public <= num_variables,
+        "{num_public} public inputs for {num_variables} variables"
+    );
+    let num_packed = Encoding::Packed.num_public_inputs(max_len);
+
+    /* the variable `0` stays in place, the others move after the packed inputs */
+    let shift = num_packed - 1;
+    let remap = |lc: HashMap<usize, BigInt>| -> Result<HashMap<usize, BigInt>> {
+        lc.into_iter()
+            .map(|(index, coeff)| {
+                ensure!(
+                    index < num_variables,
+                    "variable {index} is out of range ({num_variables} variables)"
+                );
+                Ok((if index == 0 { 0 } else { index + shift }, coeff))
+            })
+            .collect()
+    };
+    let remap_constraint = |constraint: Constraint| -> Result<Constraint> {
+        Ok(Constraint {
+            a: remap(constraint.a)?,
+            b: remap(constraint.b)?,
+            c: remap(constraint.c)?,
+        })
+    };
+    let mut constraints = r1cs
+        .0
+        .into_iter()
+        .map(remap_constraint)
+        .collect::<Result<Vec<_>>>()?;
+    let lookup = lookup
+        .map(|lookup| -> Result<_> {
+            Ok(Lookup {
+                table: lookup.table,
+                constraints: lookup
+                    .constraints
+                    .into_iter()
+                    .map(remap_constraint)
+                    .collect::<Result<_>>()?,
+            })
+        })
+        .transpose()?;
+    let values = checker::values(&assignment)?;
+    let one = || HashMap::from([(0, BigInt::from(&EF::one()))]);
+    let variable = |index: usize| HashMap::from([(index, BigInt::from(&EF::one()))]);
+
+    /* the packed inputs are the linear combinations of the moved bytes */
+    let mut variables = vec![assignment.variables[0].clone()];
+    for (input, terms) in packed_from_bytes(max_len).iter().enumerate() {
+        let mut value = EF::zero();
+        for (index, coeff) in terms {
+            value += values[*index] * coeff;
+        }
+        variables.push(BigInt::from(&value));
+        constraints.push(Constraint {
+            a: terms
+                .iter()
+                .map(|(index, coeff)| (index + shift, BigInt::from(coeff)))
+                .collect(),
+            b: one(),
+            c: variable(input + 1),
+        });
+    }
+    variables.extend(assignment.variables.into_iter().skip(1));
+
+    /* every byte is the sum of its bits */
+    let two = EF::from(2u64);
+    for byte in (1..num_public).filter(|index| *index != LEN_INPUT) {
+        let low = values[byte].to_bigint().0[0];
+        let mut sum = HashMap::new();
+        let mut power = EF::one();
+        for bit in 0..8 {
+            let index = variables.len();
+            variables.push(BigInt::from(&EF::from((low >> bit) & 1)));
+            constraints.push(Constraint {
+                a: variable(index),
+                b: variable(index),
+                c: variable(index),
+            });
+            sum.insert(index, BigInt::from(&power));
+            power *= two;
+        }
+        constraints.push(Constraint {
+            a: sum,
+            b: one(),
+            c: variable(byte + shift),
+        });
+    }
+
+    let labels = labels.map(|mut labels| {
+        let namespace = labels.namespaces.len() as u32;
+        labels.namespaces.push(NAMESPACE.to_string());
+        labels.constraints.resize(constraints.len(), namespace);
+        labels
+    });
+    debug_assert_eq!(
+        variables.len(),
+        num_variables + shift + 8 * (num_public - 2)
+    );
+    Ok((
+        R1CS(constraints),
+        Assignment {
+            variables,
+            num_public_inputs: num_packed,
+            public_encoding: Encoding::Packed,
+        },
+        lookup,
+        labels,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use hap_verifier::encoding::encode_signature;
+    use hap_verifier::SignatureInput;
+
+    use super::*;
+    use crate::r1cs_provider::checker::{check, Violation};
+
+    const MAX_LEN: usize = 40;
+
+    fn signature() -> SignatureInput {
+        SignatureInput {
+            public_key: (0..64).collect(),
+            message: vec![7, 9],
+            signature: (64..128).collect(),
+        }
+    }
+
+    /// A circuit with byte encoded public inputs, and the private product of the first two bytes
+    /// of the message.
+    fn circuit(message: [u64; 2]) -> Result<(R1CS, Assignment)> {
+        let num_public = Encoding::Bytes.num_public_inputs(MAX_LEN);
+        let mut variables = encode_signature(&signature(), MAX_LEN, Encoding::Bytes)?
+            .iter()
+            .map(BigInt::from)
+            .collect::<Vec<_>>();
+        variables[130] = BigInt([message[0], 0, 0, 0]);
+        variables[131] = BigInt([message[1], 0, 0, 0]);
+        variables.push(BigInt([message[0] * message[1], 0, 0, 0]));
+        let variable = |index| HashMap::from([(index, BigInt([1, 0, 0, 0]))]);
+        let r1cs = R1CS(vec![Constraint {
+            a: variable(130),
+            b: variable(131),
+            c: variable(num_public),
+        }]);
+        let assignment = Assignment {
+            variables,
+            num_public_inputs: num_public,
+            public_encoding: Encoding::Bytes,
+        };
+        Ok((r1cs, assignment))
+    }
+
+    #[test]
+    fn test_repack() -> Result<()> {
+        let (r1cs, assignment) = circuit([7, 9])?;
+        assert_eq!(check(&r1cs, &assignment, None)?, None);
+        let labels = Labels {
+            namespaces: vec!["product".to_string()],
+            constraints: vec![0],
+            lookup_constraints: vec![],
+        };
+        let (r1cs, assignment, _, labels) = repack(r1cs, assignment, None, Some(labels))?;
+        assert_eq!(check(&r1cs, &assignment, None)?, None);
+
+        let labels = labels.unwrap();
+        labels.validate(r1cs.0.len(), 0)?;
+        assert_eq!(labels.constraint(0), Some("product"));
+        assert_eq!(labels.constraint(1), Some(NAMESPACE));
+
+        let inputs = checker::values(&assignment)?;
+        assert_eq!(
+            inputs[..assignment.num_public_inputs],
+            encode_signature(&signature(), MAX_LEN, Encoding::Packed)?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_repack_bytes_out_of_range() -> Result<()> {
+        /* 263 + 256 * 8 packs as 7 + 256 * 9 */
+        let (r1cs, assignment) = circuit([263, 8])?;
+        assert_eq!(check(&r1cs, &assignment, None)?, None);
+        let (r1cs, assignment, _, _) = repack(r1cs, assignment, None, None)?;
+        let inputs = checker::values(&assignment)?;
+        assert_eq!(
+            inputs[..assignment.num_public_inputs],
+            encode_signature(&signature(), MAX_LEN, Encoding::Packed)?
+        );
+        assert!(matches!(
+            check(&r1cs, &assignment, None)?,
+            Some(Violation::Constraint(_))
+        ));
+
+        let (_, mut assignment) = circuit([7, 9])?;
+        assignment.public_encoding = Encoding::Packed;
+        assert!(repack(R1CS(vec![]), assignment, None, None).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::{BTreeMap, HashMap};
+
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+use snarkvm_algorithms::snark::varuna::{AHPForR1CS, SNARKMode};
+use snarkvm_curves::bls12_377::Fr;
+
+use super::balance::{self, BalanceReport, BalanceStrategy};
+use super::deserialize::{Assignment, BigInt, Constraint, Labels, Lookup, R1CS};
+
+/// Size of a circuit, computed from the imported R1CS.
+///
+/// The report is serialized as JSON to track regressions of the circuit size. The statistics do
+/// not depend on the hiding mode of Varuna, except for the degree of the setup, see
+/// [`CircuitStats::max_degree`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CircuitStats {
+    /// Number of R1CS constraints enforced by the builder.
+    pub num_constraints: usize,
+    /// Constraints between constants, which the builder checks and skips. They are not counted
+    /// in the other statistics.
+    #[serde(default)]
+    pub num_constant_rows: usize,
+    /// Number of public variables, including the constant `1`.
+    pub num_public: usize,
+    pub num_private: usize,
+    /// Non zeros per matrix after balancing A and B, lookup constraints are counted into A.
+    pub balance: BalanceReport,
+    pub num_lookup_constraints: usize,
+    pub lookup_table_size: usize,
+    /// Number of constraints (R1CS and lookup) by non zeros in the row, rounded up to a power
+    /// of two.
+    pub row_density: BTreeMap<usize, usize>,
+    pub constraint_domain_size: usize,
+    pub variable_domain_size: usize,
+    /// Size of the largest of the non zero domains of A, B and C.
+    pub non_zero_domain_size: usize,
+    /// Constraints by gadget, if the circuit has labels.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub by_label: BTreeMap<String, LabelStats>,
+}
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct LabelStats {
+    pub num_constraints: usize,
+    pub num_lookup_constraints: usize,
+    pub num_non_zero: usize,
+}
+
+impl CircuitStats {
+    pub fn new(
+        r1cs: &R1CS,
+        assignment: &Assignment,
+        lookup: Option<&Lookup>,
+        labels: Option<&Labels>,
+        strategy: BalanceStrategy,
+    ) -> Result<Self> {
+        let swaps = plan_swaps(strategy, r1cs, lookup);
+        Self::with_swaps(r1cs, assignment, lookup, labels, strategy, &swaps)
+    }
+
+    /// The statistics of the circuit balanced with `swaps`, see [`plan_swaps`].
+    pub(crate) fn with_swaps(
+        r1cs: &R1CS,
+        assignment: &Assignment,
+        lookup: Option<&Lookup>,
+        labels: Option<&Labels>,
+        strategy: BalanceStrategy,
+        swaps: &[bool],
+    ) -> Result<Self> {
+        let enforced = r1cs
+            .0
+            .iter()
+            .map(|constraint| !is_constant_row(constraint))
+            .collect::<Vec<_>>();
+        /* the enforced constraints with their index, and their swaps */
+        let constraints = || {
+            r1cs.0
+                .iter()
+                .enumerate()
+                .filter(|(index, _)| enforced[*index])
+        };
+        let swaps = swaps
+            .iter()
+            .zip(&enforced)
+            .filter(|(_, enforced)| **enforced)
+            .map(|(swap, _)| *swap)
+            .collect::<Vec<_>>();
+
+        let rows = constraints()
+            .map(|(_, constraint)| (num_non_zero(&constraint.a), num_non_zero(&constraint.b)))
+            .collect::<Vec<_>>();
+        let nnz_c = constraints()
+            .map(|(_, constraint)| num_non_zero(&constraint.c))
+            .sum();
+        let nnz_lookup_a = lookup.map_or(0, |lookup| {
+            lookup
+                .constraints
+                .iter()
+                .map(|constraint| num_non_zero(&constraint.a))
+                .sum()
+        });
+        let balance = balance::report(strategy, &rows, &swaps, nnz_lookup_a, nnz_c);
+
+        let mut row_density = BTreeMap::new();
+        let lookup_constraints = lookup.map_or(&[][..], |lookup| &lookup.constraints[..]);
+        for constraint in constraints()
+            .map(|(_, constraint)| constraint)
+            .chain(lookup_constraints)
+        {
+            let nnz = num_non_zero(&constraint.a)
+                + num_non_zero(&constraint.b)
+                + num_non_zero(&constraint.c);
+            *row_density.entry(nnz.next_power_of_two()).or_insert(0) += 1;
+        }
+
+        let mut by_label = BTreeMap::<String, LabelStats>::new();
+        if let Some(labels) = labels {
+            labels.validate(r1cs.0.len(), lookup_constraints.len())?;
+            for (index, constraint) in constraints() {
+                let label = labels.constraint(index).unwrap_or_default();
+                let entry = by_label.entry(label.to_string()).or_default();
+                entry.num_constraints += 1;
+                entry.num_non_zero += num_non_zero(&constraint.a)
+                    + num_non_zero(&constraint.b)
+                    + num_non_zero(&constraint.c);
+            }
+            for (index, constraint) in lookup_constraints.iter().enumerate() {
+                let label = labels.lookup_constraint(index).unwrap_or_default();
+                let entry = by_label.entry(label.to_string()).or_default();
+                entry.num_lookup_constraints += 1;
+                entry.num_non_zero += num_non_zero(&constraint.a);
+            }
+        }
+
+        let num_constraints = rows.len();
+        let num_constant_rows = r1cs.0.len() - num_constraints;
+        let num_lookup_constraints = lookup_constraints.len();
+        let num_public = assignment.num_public_inputs;
+        let num_private = assignment.variables.len().saturating_sub(num_public);
+        let max_non_zero = balance.nnz_a.max(balance.nnz_b).max(balance.nnz_c);
+
+        Ok(Self {
+            num_constraints,
+            num_constant_rows,
+            num_public,
+            num_private,
+            balance,
+            num_lookup_constraints,
+            lookup_table_size: lookup.map_or(0, |lookup| lookup.table.0.len()),
+            row_density,
+            constraint_domain_size: (num_constraints + num_lookup_constraints).next_power_of_two(),
+            variable_domain_size: (num_public + num_private).next_power_of_two(),
+            non_zero_domain_size: max_non_zero.next_power_of_two(),
+            by_label,
+        })
+    }
+
+    /// Maximum degree required from the universal setup for the hiding mode `SM`, which needs
+    /// more powers for [`VarunaHidingMode`](snarkvm_algorithms::snark::varuna::VarunaHidingMode).
+    pub fn max_degree<SM: SNARKMode>(&self) -> Result<usize> {
+        let balance = &self.balance;
+        AHPForR1CS::<Fr, SM>::max_degree(
+            self.num_constraints + self.num_lookup_constraints,
+            self.num_public + self.num_private,
+            balance.nnz_a.max(balance.nnz_b).max(balance.nnz_c),
+        )
+        .map_err(|e| anyhow!("failed to compute the max degree: {e:?}"))
+    }
+
+    /// The statistics as JSON, with the maximum degree for the hiding mode `SM`.
+    pub fn to_json<SM: SNARKMode>(&self) -> Result<String> {
+        #[derive(Serialize)]
+        struct Json<'a> {
+            #[serde(flatten)]
+            stats: &'a CircuitStats,
+            max_degree: usize,
+        }
+        Ok(serde_json::to_string_pretty(&Json {
+            stats: self,
+            max_degree: self.max_degree::<SM>()?,
+        })?)
+    }
+}
+
+/// Decides which constraints the builder swaps.
+///
+/// The plan counts the terms of the linear combinations as exported, zero coefficients included,
+/// as the builder always did: counting the non zeros instead would change the swaps of circuits
+/// with zero coefficients, hence their keys. The non zeros are only used for the report.
+pub(crate) fn plan_swaps(
+    strategy: BalanceStrategy,
+    r1cs: &R1CS,
+    lookup: Option<&Lookup>,
+) -> Vec<bool> {
+    let rows = r1cs
+        .0
+        .iter()
+        .map(|constraint| (constraint.a.len(), constraint.b.len()))
+        .collect::<Vec<_>>();
+    let len_c = r1cs.0.iter().map(|constraint| constraint.c.len()).sum();
+    let len_lookup_a = lookup.map_or(0, |lookup| {
+        lookup
+            .constraints
+            .iter()
+            .map(|constraint| constraint.a.len())
+            .sum()
+    });
+    balance::plan_swaps(strategy, &rows, len_lookup_a, len_c)
+}
+
+/// Whether the terms of the constraint are all constants, such rows are not enforced by the
+/// builder.
+fn is_constant_row(constraint: &Constraint) -> bool {
+    [&constraint.a, &constraint.b, &constraint.c]
+        .iter()
+        .all(|lc| {
+            lc.iter()
+                .all(|(index, coeff)| *index == 0 || coeff.0 == [0; 4])
+        })
+}
+
+/// Number of non zero coefficients of a linear combination, as counted by the builder.
+pub(crate) fn num_non_zero(lc: &HashMap<usize, BigInt>) -> usize {
+    lc.values().filter(|coeff| coeff.0 != [0; 4]).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::r1cs_provider::fixtures;
+
+    #[test]
+    fn test_stats() -> Result<()> {
+        let (r1cs, assignment, lookup) = fixtures::parse(fixtures::SATISFIED)?;
+        let labels = fixtures::labels()?;
+        let stats = CircuitStats::new(
+            &r1cs,
+            &assignment,
+            lookup.as_ref(),
+            Some(&labels),
+            BalanceStrategy::None,
+        )?;
+
+        assert_eq!(stats.num_constraints, 3);
+        assert_eq!(stats.num_public, 3);
+        assert_eq!(stats.num_private, 3);
+        assert_eq!(stats.num_lookup_constraints, 1);
+        assert_eq!(stats.lookup_table_size, 16);
+        /* the lookup constraint adds its 2 non zeros to A */
+        assert_eq!(
+            (
+                stats.balance.nnz_a,
+                stats.balance.nnz_b,
+                stats.balance.nnz_c
+            ),
+            (7, 3, 3)
+        );
+        assert_eq!(stats.row_density, BTreeMap::from([(2, 1), (4, 2), (8, 1)]));
+        assert_eq!(stats.constraint_domain_size, 4);
+        assert_eq!(stats.variable_domain_size, 8);
+        assert_eq!(stats.non_zero_domain_size, 8);
+
+        let by_label = stats
+            .by_label
+            .iter()
+            .map(|(label, stats)| {
+                (
+                    label.as_str(),
+                    stats.num_constraints,
+                    stats.num_lookup_constraints,
+                    stats.num_non_zero,
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            by_label,
+            vec![("cube", 2, 0, 6), ("range", 0, 1, 2), ("sum", 1, 0, 5)]
+        );
+        Ok(())
+    }
+}
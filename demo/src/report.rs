@@ -0,0 +1,338 @@
+//! Machine-readable timing and memory report of a run.
+//!
+//! The phases of the pipeline record their duration next to their spans, see [`crate::trace`],
+//! into each [`Recording`] in progress. Phases run for every instance, on several threads, so a
+//! phase reports the sum of its durations and how many times it ran; the wall-clock time of the
+//! whole run is reported separately. Concurrent recordings in the same process do not reset each
+//! other, but each of them also gets the phases of the other runs, and only the first one
+//! measures the peak memory, see [`Recording::start`].
+//!
+//! The rounds of `prove_batch` are internal to snarkVM, only `aleo_std_profiler` times them. With
+//! the `profiler` feature its timers are printed, and the printed output is added to a report as
+//! the sub-phases of [`Phase::Prove`], see [`Report::add_profiler_output`] and
+//! `capture_stdout`.
+
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+
+/// A phase of the pipeline.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Phase {
+    /// Running gnark-circuit-gen.
+    Generator,
+    /// Parsing the CBOR files of the generator.
+    Parse,
+    /// Building the circuit in the snarkVM environment.
+    Synthesis,
+    /// Ejecting the assignment from the environment and sharing its base.
+    Assignment,
+    /// Preparing the universal prover from the SRS.
+    ProverSetup,
+    /// `prove_batch`.
+    Prove,
+    /// Collecting the public inputs of the instances.
+    PublicInputs,
+    /// `verify_batch`.
+    Verify,
+}
+
+/// The durations of a phase.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PhaseTiming {
+    /// Number of times the phase ran.
+    pub count: usize,
+    /// Sum of the durations, in seconds.
+    pub total_secs: f64,
+    /// Longest duration, in seconds.
+    pub max_secs: f64,
+}
+
+impl PhaseTiming {
+    fn add(&mut self, secs: f64) {
+        self.count += 1;
+        self.total_secs += secs;
+        self.max_secs = self.max_secs.max(secs);
+    }
+}
+
+/// The report of a run, see the [module documentation](self).
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct Report {
+    pub wall_secs: f64,
+    pub phases: BTreeMap<Phase, PhaseTiming>,
+    /// Sub-phases of [`Phase::Prove`] by timer name, empty unless built with the `profiler`
+    /// feature or added with [`Report::add_profiler_output`].
+    pub prover_phases: BTreeMap<String, PhaseTiming>,
+    /// Peak resident set size of the process in bytes since the recording started. `None` if the
+    /// platform does not report it, or if the recording started while another one was in
+    /// progress, see [`Recording::start`].
+    pub peak_rss_bytes: Option<u64>,
+}
+
+impl Report {
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+
+    /// Adds the timers of `aleo_std_profiler` to the prover sub-phases. `output` is what the run
+    /// printed with the `profiler` feature, the lines which do not end a timer are ignored.
+    pub fn add_profiler_output(&mut self, output: &str) {
+        for line in output.lines() {
+            if let Some((name, secs)) = parse_profiler_line(line) {
+                self.prover_phases.entry(name).or_default().add(secs);
+            }
+        }
+    }
+}
+
+/// The name and duration in seconds of the end of a timer of `aleo_std_profiler`, a line like
+/// `··End:     Varuna::Prover .............1375.194s`, possibly with colors.
+fn parse_profiler_line(line: &str) -> Option<(String, f64)> {
+    let mut plain = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        /* skip the color escapes, `ESC [ ... m` */
+        if c == '\x1b' {
+            chars.by_ref().find(|c| *c == 'm');
+        } else {
+            plain.push(c);
+        }
+    }
+    let (_, timer) = plain.split_once("End:")?;
+    let timer = timer.trim();
+    let (value, unit) = [("ms", 1e-3), ("µs", 1e-6), ("ns", 1e-9), ("s", 1.0)]
+        .into_iter()
+        .find_map(|(suffix, unit)| Some((timer.strip_suffix(suffix)?, unit)))?;
+
+    /* the name is padded with dots up to the duration, which has at most one dot */
+    let trim_digits = |s: &str| s.trim_end_matches(|c: char| c.is_ascii_digit()).len();
+    let mut start = trim_digits(value);
+    if let Some(integer) = value[..start].strip_suffix('.') {
+        if trim_digits(integer) < integer.len() {
+            start = trim_digits(integer);
+        }
+    }
+    let secs = value[start..].parse::<f64>().ok()? * unit;
+    let name = value[..start].trim_end_matches('.').trim();
+    (!name.is_empty()).then(|| (name.to_string(), secs))
+}
+
+/* The phases of each recording in progress, by id */
+static RECORDINGS: Mutex<BTreeMap<u64, BTreeMap<Phase, PhaseTiming>>> = Mutex::new(BTreeMap::new());
+static NEXT_RECORDING: AtomicU64 = AtomicU64::new(0);
+
+impl Phase {
+    /// Records the duration of the phase until the returned guard is dropped.
+    pub fn start(self) -> PhaseGuard {
+        PhaseGuard {
+            phase: self,
+            start: Instant::now(),
+        }
+    }
+
+    /// Adds `duration` to the recordings in progress.
+    pub fn record(self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for phases in RECORDINGS.lock().unwrap().values_mut() {
+            phases.entry(self).or_default().add(secs);
+        }
+    }
+}
+
+/// Records the duration of a phase when dropped, see [`Phase::start`].
+pub struct PhaseGuard {
+    phase: Phase,
+    start: Instant,
+}
+
+impl Drop for PhaseGuard {
+    fn drop(&mut self) {
+        self.phase.record(self.start.elapsed());
+    }
+}
+
+/// Collects the phases recorded from now on, see [`Recording::finish`].
+pub struct Recording {
+    id: u64,
+    start: Instant,
+    peak_rss_reset: bool,
+}
+
+impl Recording {
+    /// Starts a new report, and resets the peak resident set size of the process to its current
+    /// size, see [`reset_peak_rss`].
+    ///
+    /// The peak is shared by the whole process: it is only reset if no other recording is in
+    /// progress, since it would lose the peak of that recording. A recording which starts while
+    /// another one is in progress does not report the peak memory.
+    pub fn start() -> Self {
+        let id = NEXT_RECORDING.fetch_add(1, Ordering::Relaxed);
+        let mut recordings = RECORDINGS.lock().unwrap();
+        let peak_rss_reset = recordings.is_empty() && reset_peak_rss();
+        recordings.insert(id, BTreeMap::new());
+        Self {
+            id,
+            start: Instant::now(),
+            peak_rss_reset,
+        }
+    }
+
+    pub fn finish(self) -> Report {
+        Report {
+            wall_secs: self.start.elapsed().as_secs_f64(),
+            phases: RECORDINGS
+                .lock()
+                .unwrap()
+                .remove(&self.id)
+                .unwrap_or_default(),
+            prover_phases: BTreeMap::new(),
+            peak_rss_bytes: peak_rss_bytes().filter(|_| self.peak_rss_reset),
+        }
+    }
+}
+
+impl Drop for Recording {
+    fn drop(&mut self) {
+        RECORDINGS.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Resets the peak resident set size of the process, by writing `5` to `/proc/self/clear_refs`
+/// on Linux. Returns whether it did. This also resets the peak of the recordings in progress.
+pub fn reset_peak_rss() -> bool {
+    std::fs::write("/proc/self/clear_refs", "5").is_ok()
+}
+
+/// The peak resident set size of the process since it started or since [`reset_peak_rss`],
+/// `VmHWM` in `/proc/self/status` on Linux.
+pub fn peak_rss_bytes() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb = line
+        .trim_start_matches("VmHWM:")
+        .trim()
+        .trim_end_matches("kB")
+        .trim()
+        .parse::<u64>()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+/// Runs `f` with the standard output of the process redirected to a file, and returns what was
+/// printed meanwhile, which is then printed as usual. `aleo_std_profiler` only prints its
+/// timers, this is how [`crate::prove_and_verify`] reads them.
+///
+/// The output of the other threads is captured too, and concurrent captures wait for each
+/// other. If the output can't be redirected, `f` runs as is and nothing is captured.
+#[cfg(all(feature = "profiler", unix))]
+pub fn capture_stdout<R>(f: impl FnOnce() -> R) -> (R, String) {
+    use std::io::{Read as _, Seek as _, Write as _};
+    use std::os::unix::io::AsRawFd as _;
+
+    /// Restores the standard output when dropped, also if `f` panics.
+    struct Redirect {
+        saved: i32,
+    }
+
+    impl Drop for Redirect {
+        fn drop(&mut self) {
+            let _ = std::io::stdout().flush();
+            /* SAFETY: `saved` is a descriptor duplicated by `redirect`, and closed only here */
+            unsafe {
+                libc::dup2(self.saved, libc::STDOUT_FILENO);
+                libc::close(self.saved);
+            }
+        }
+    }
+
+    fn redirect(file: &std::fs::File) -> Result<Redirect> {
+        std::io::stdout().flush()?;
+        /* SAFETY: only file descriptors are passed, the errors are checked */
+        let saved = unsafe { libc::dup(libc::STDOUT_FILENO) };
+        if saved < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        let redirect = Redirect { saved };
+        if unsafe { libc::dup2(file.as_raw_fd(), libc::STDOUT_FILENO) } < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(redirect)
+    }
+
+    static CAPTURE: Mutex<()> = Mutex::new(());
+    let _capture = CAPTURE.lock().unwrap_or_else(|err| err.into_inner());
+    let capture = tempfile::tempfile()
+        .map_err(anyhow::Error::from)
+        .and_then(|file| Ok((redirect(&file)?, file)));
+    let (redirect, mut file) = match capture {
+        Ok(capture) => capture,
+        Err(err) => {
+            log::warn!("the standard output can't be captured: {err}");
+            return (f(), String::new());
+        }
+    };
+    let result = f();
+    drop(redirect);
+
+    let mut output = vec![];
+    if let Err(err) = file.rewind().and_then(|_| file.read_to_end(&mut output)) {
+        log::warn!("the captured standard output can't be read: {err}");
+    }
+    let output = String::from_utf8_lossy(&output).into_owned();
+    print!("{output}");
+    (result, output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* Other tests of the crate may record phases at the same time, hence the `>=` */
+    #[test]
+    fn test_report() -> Result<()> {
+        let recording = Recording::start();
+        {
+            let _phase = Phase::Parse.start();
+        }
+        /* a concurrent recording does not reset the first one, nor its peak memory */
+        let concurrent = Recording::start();
+        Phase::Parse.record(Duration::from_millis(10));
+        let concurrent = concurrent.finish();
+        assert!(concurrent.phases[&Phase::Parse].count >= 1);
+        assert_eq!(concurrent.peak_rss_bytes, None);
+        let report = recording.finish();
+
+        let parse = &report.phases[&Phase::Parse];
+        assert!(parse.count >= 2);
+        assert!(parse.total_secs >= 0.01);
+        assert!(report.to_json()?.contains("\"parse\""));
+        assert_ne!(report.peak_rss_bytes, Some(0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_profiler_output() {
+        let mut report = Report::default();
+        report.add_profiler_output(
+            "Start:   Varuna::Prover\n\
+             ··End:     \x1b[1mVaruna::Prover::FirstRound\x1b[0m .......\x1b[1m12.500ms\x1b[0m\n\
+             ··End:     Varuna::Prover::FirstRound ...........10.500ms\n\
+             ··End:     Round 2 commitments ..................850ns\n\
+             End:     Varuna::Prover ...........................1375.194s\n\
+             unrelated output\n",
+        );
+        let round = &report.prover_phases["Varuna::Prover::FirstRound"];
+        assert_eq!(round.count, 2);
+        assert!((round.total_secs - 0.023).abs() < 1e-9);
+        assert!((round.max_secs - 0.0125).abs() < 1e-9);
+        assert!((report.prover_phases["Round 2 commitments"].total_secs - 850e-9).abs() < 1e-15);
+        assert!((report.prover_phases["Varuna::Prover"].total_secs - 1375.194).abs() < 1e-9);
+        assert_eq!(report.prover_phases.len(), 3);
+    }
+}
@@ -0,0 +1,663 @@
+//! Local proving service: HTTP/JSON over localhost, with a persistent job queue.
+//!
+//! | Request                 | Response                                         |
+//! |-------------------------|--------------------------------------------------|
+//! | `POST /jobs`            | `202 {"id": 1}`, the body is a [`JobRequest`]    |
+//! | `GET /jobs`             | the status of all jobs                           |
+//! | `GET /jobs/<id>`        | `{"id": 1, "status": "queued", "error": null}`   |
+//! | `GET /jobs/<id>/proof`  | the [`ProofBundle`], `409` until the job is done |
+//!
+//! Jobs are proved one at a time, since a single proof already uses all cores. Each job is
+//! stored as `job-<id>.json` in the queue directory and its result as `proof-<id>.json`, so that
+//! queued jobs survive a restart of the server.
+//!
+//! Connections are handled by a fixed pool of threads, with a timeout on reads and writes; when
+//! all of them are busy, new connections are answered `503`. A job proves at most
+//! [`MAX_BATCH_SIZE`] instances.
+
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::{BufRead, BufReader, ErrorKind, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::mpsc::{self, TrySendError};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use hap_verifier::Encoding;
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use snarkvm_algorithms::snark::varuna::{SNARKMode, VarunaHidingMode};
+use snarkvm_circuit::Circuit;
+
+use crate::api::{self, ProveJob, ProverConfig};
+use crate::bucket::{self, BucketRegistry, KeyCache};
+use crate::bundle::ProofBundle;
+use crate::input::SignatureInput;
+use crate::TestCase;
+
+/// Maximum size of a request body.
+const MAX_BODY_SIZE: usize = 64 << 20;
+
+/// Maximum number of instances of a job: the batch, or the number of signatures.
+pub const MAX_BATCH_SIZE: usize = 1024;
+
+/// Number of threads handling the connections.
+const NUM_CONNECTION_THREADS: usize = 8;
+
+/// Timeout of the reads and writes on a connection.
+const IO_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How often the listener checks that the worker is still running.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The body of `POST /jobs`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum JobRequest {
+    /// `{"test_case": "test1", "batch": 4}`: `batch` instances of the circuit of a test case,
+    /// with an optional `"encoding"` of the public inputs.
+    Batch {
+        test_case: String,
+        batch: u32,
+        #[serde(default)]
+        encoding: Encoding,
+    },
+    /// `{"signatures": [...]}`: signatures grouped by message length, see [`crate::bucket`].
+    Signatures { signatures: Vec<SignatureInput> },
+}
+
+impl JobRequest {
+    /// Number of instances to prove.
+    pub fn num_instances(&self) -> usize {
+        match self {
+            JobRequest::Batch { batch, .. } => *batch as usize,
+            JobRequest::Signatures { signatures } => signatures.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobStatus {
+    Queued,
+    Running,
+    Done,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Job {
+    pub id: u64,
+    pub request: JobRequest,
+    pub status: JobStatus,
+    pub error: Option<String>,
+}
+
+impl Job {
+    fn summary(&self) -> serde_json::Value {
+        serde_json::json!({
+            "id": self.id,
+            "status": self.status,
+            "error": self.error,
+        })
+    }
+}
+
+/// Proves the jobs of the queue.
+pub trait Prover: Sync {
+    /// Checks a request before it is queued, e.g. that the keys of its circuits are loaded.
+    fn validate(&self, request: &JobRequest) -> Result<()>;
+
+    fn prove(&self, request: &JobRequest) -> Result<ProofBundle>;
+}
+
+/// Proves with keys loaded at startup. Requests for circuits without keys are rejected instead
+/// of compiling the keys in the server. The proofs are of the hiding mode of the keys, `SM`.
+pub struct KeyProver<'a, SM: SNARKMode = VarunaHidingMode> {
+    pub registry: BucketRegistry,
+    pub keys: KeyCache<'a, SM>,
+    pub config: ProverConfig,
+}
+
+impl<SM: SNARKMode> Prover for KeyProver<'_, SM> {
+    fn validate(&self, request: &JobRequest) -> Result<()> {
+        let circuits = match request {
+            JobRequest::Batch {
+                test_case,
+                batch,
+                encoding,
+            } => {
+                ensure!(*batch > 0, "the batch is empty");
+                vec![(TestCase::from_str(test_case)?, *encoding)]
+            }
+            JobRequest::Signatures { signatures } => {
+                ensure!(!signatures.is_empty(), "no signatures");
+                for (index, signature) in signatures.iter().enumerate() {
+                    signature
+                        .validate()
+                        .with_context(|| format!("invalid signature {index}"))?;
+                }
+                let msgs = signatures
+                    .iter()
+                    .map(|signature| &signature.message)
+                    .collect::<Vec<_>>();
+                self.registry
+                    .group(&msgs)?
+                    .into_iter()
+                    .map(|(bucket, _)| (bucket.test_case, bucket.encoding))
+                    .collect()
+            }
+        };
+        for (test_case, encoding) in circuits {
+            self.keys.require(test_case, encoding)?;
+        }
+        Ok(())
+    }
+
+    fn prove(&self, request: &JobRequest) -> Result<ProofBundle> {
+        self.validate(request)?;
+        match request {
+            JobRequest::Batch {
+                test_case,
+                batch,
+                encoding,
+            } => {
+                let test_case = TestCase::from_str(test_case)?;
+                let keys = self.keys.require(test_case, *encoding)?;
+                let job = ProveJob {
+                    test_case,
+                    pk: &keys.0,
+                    batch_num: *batch,
+                    signatures: None,
+                    encoding: *encoding,
+                };
+                let (proof, inputs) =
+                    api::prove_jobs::<Circuit, _>(self.keys.urs(), &[job], &self.config);
+                let name = test_case.circuit_name(*encoding);
+                ProofBundle::new(&proof, &[(&name, &inputs[0])])
+            }
+            JobRequest::Signatures { signatures } => {
+                bucket::prove(&self.registry, &self.keys, signatures, &self.config)?.bundle()
+            }
+        }
+    }
+}
+
+/// Jobs persisted in a directory.
+pub struct JobQueue {
+    dir: PathBuf,
+    state: Mutex<QueueState>,
+    ready: Condvar,
+}
+
+#[derive(Default)]
+struct QueueState {
+    jobs: BTreeMap<u64, Job>,
+    pending: VecDeque<u64>,
+}
+
+impl JobQueue {
+    /// Opens the queue stored in `dir`. Jobs which were running when the server stopped are
+    /// queued again.
+    pub fn open(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {dir:?}"))?;
+
+        let mut state = QueueState::default();
+        for entry in fs::read_dir(&dir)? {
+            let path = entry?.path();
+            let is_job = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .map_or(false, |name| {
+                    name.starts_with("job-") && name.ends_with(".json")
+                });
+            if !is_job {
+                continue;
+            }
+            let content = fs::read(&path).with_context(|| format!("failed to read {path:?}"))?;
+            let mut job: Job = serde_json::from_slice(&content)
+                .with_context(|| format!("failed to parse {path:?}"))?;
+            if job.status == JobStatus::Running {
+                job.status = JobStatus::Queued;
+            }
+            state.jobs.insert(job.id, job);
+        }
+        state.pending = state
+            .jobs
+            .values()
+            .filter(|job| job.status == JobStatus::Queued)
+            .map(|job| job.id)
+            .collect();
+        info!(
+            "opened job queue {dir:?}: {} jobs, {} queued",
+            state.jobs.len(),
+            state.pending.len()
+        );
+
+        Ok(Self {
+            dir,
+            state: Mutex::new(state),
+            ready: Condvar::new(),
+        })
+    }
+
+    /// The state survives a panic of another thread holding the lock: it is only changed after
+    /// the job files are written, so it is consistent with them.
+    fn state(&self) -> MutexGuard<QueueState> {
+        self.state.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn submit(&self, request: JobRequest) -> Result<u64> {
+        let mut state = self.state();
+        let id = state.jobs.keys().next_back().map_or(1, |id| id + 1);
+        let job = Job {
+            id,
+            request,
+            status: JobStatus::Queued,
+            error: None,
+        };
+        self.store(&job)?;
+        state.jobs.insert(id, job);
+        state.pending.push_back(id);
+        self.ready.notify_one();
+        Ok(id)
+    }
+
+    pub fn job(&self, id: u64) -> Option<Job> {
+        self.state().jobs.get(&id).cloned()
+    }
+
+    pub fn jobs(&self) -> Vec<Job> {
+        self.state().jobs.values().cloned().collect()
+    }
+
+    /// The proof bundle of a job which is done.
+    pub fn result(&self, id: u64) -> Result<ProofBundle> {
+        ProofBundle::read(self.result_path(id))
+    }
+
+    /// Waits for the next queued job and marks it as running.
+    fn next(&self) -> Result<Job> {
+        let mut state = self.state();
+        loop {
+            if let Some(id) = state.pending.pop_front() {
+                let job = state.jobs.get_mut(&id).expect("pending jobs are known");
+                job.status = JobStatus::Running;
+                let job = job.clone();
+                self.store(&job)?;
+                return Ok(job);
+            }
+            state = self
+                .ready
+                .wait(state)
+                .unwrap_or_else(PoisonError::into_inner);
+        }
+    }
+
+    fn finish(&self, id: u64, result: Result<ProofBundle>) -> Result<()> {
+        let result = result.and_then(|bundle| bundle.write(self.result_path(id)));
+        let mut state = self.state();
+        let job = state.jobs.get_mut(&id).expect("running jobs are known");
+        match result {
+            Ok(()) => job.status = JobStatus::Done,
+            Err(e) => {
+                job.status = JobStatus::Failed;
+                job.error = Some(format!("{e:#}"));
+            }
+        }
+        self.store(job)
+    }
+
+    fn store(&self, job: &Job) -> Result<()> {
+        /* write then rename, so that a crash never leaves a truncated job */
+        let path = self.dir.join(format!("job-{}.json", job.id));
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, serde_json::to_vec(job)?)
+            .with_context(|| format!("failed to write {tmp:?}"))?;
+        fs::rename(&tmp, &path).with_context(|| format!("failed to write {path:?}"))
+    }
+
+    fn result_path(&self, id: u64) -> PathBuf {
+        self.dir.join(format!("proof-{id}.json"))
+    }
+}
+
+/// Proves the queued jobs, one at a time, forever.
+pub fn run_worker(queue: &JobQueue, prover: &impl Prover) -> Result<()> {
+    loop {
+        let job = queue.next()?;
+        info!("job {}: start", job.id);
+        let result = panic::catch_unwind(AssertUnwindSafe(|| prover.prove(&job.request)))
+            .unwrap_or_else(|_| Err(anyhow!("the prover panicked")));
+        match &result {
+            Ok(_) => info!("job {}: done", job.id),
+            Err(e) => warn!("job {}: failed: {e:#}", job.id),
+        }
+        queue.finish(job.id, result)?;
+    }
+}
+
+/// Serves the HTTP API on `listener` and proves the jobs in the background. Never returns
+/// unless the worker fails to store a job.
+pub fn serve(listener: TcpListener, queue: &JobQueue, prover: &impl Prover) -> Result<()> {
+    info!("listening on {}", listener.local_addr()?);
+    /* non blocking, so that the loop notices when the worker stops */
+    listener.set_nonblocking(true)?;
+    thread::scope(|scope| {
+        let worker = scope.spawn(|| run_worker(queue, prover));
+
+        let (sender, receiver) = mpsc::sync_channel::<TcpStream>(NUM_CONNECTION_THREADS);
+        let receiver = Mutex::new(receiver);
+        for _ in 0..NUM_CONNECTION_THREADS {
+            let receiver = &receiver;
+            scope.spawn(move || loop {
+                let stream = receiver
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .recv();
+                let Ok(stream) = stream else {
+                    return;
+                };
+                if let Err(e) = handle_connection(stream, queue, prover) {
+                    warn!("connection failed: {e:#}");
+                }
+            });
+        }
+
+        while !worker.is_finished() {
+            let stream = match listener.accept() {
+                Ok((stream, _)) => stream,
+                Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                    thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("failed to accept a connection: {e}");
+                    continue;
+                }
+            };
+            if let Err(e) = configure(&stream) {
+                warn!("failed to configure a connection: {e:#}");
+                continue;
+            }
+            if let Err(TrySendError::Full(stream)) = sender.try_send(stream) {
+                let response = Response::error(503, "the server is busy");
+                if let Err(e) = write_response(stream, &response) {
+                    warn!("connection failed: {e:#}");
+                }
+            }
+        }
+        /* stops the connection threads */
+        drop(sender);
+        worker.join().expect("the worker does not panic")
+    })
+}
+
+fn configure(stream: &TcpStream) -> Result<()> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(IO_TIMEOUT))?;
+    stream.set_write_timeout(Some(IO_TIMEOUT))?;
+    Ok(())
+}
+
+struct Request {
+    method: String,
+    path: String,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    body: String,
+}
+
+impl Response {
+    fn json(status: u16, value: &impl Serialize) -> Self {
+        Self {
+            status,
+            body: serde_json::to_string(value).expect("values are serializable"),
+        }
+    }
+
+    fn error(status: u16, message: impl std::fmt::Display) -> Self {
+        Self::json(status, &serde_json::json!({ "error": message.to_string() }))
+    }
+}
+
+fn handle_connection(stream: TcpStream, queue: &JobQueue, prover: &impl Prover) -> Result<()> {
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let response = match read_request(&mut reader) {
+        Ok(request) => route(&request, queue, prover),
+        Err(e) => Response::error(400, format!("{e:#}")),
+    };
+    write_response(stream, &response)
+}
+
+fn route(request: &Request, queue: &JobQueue, prover: &impl Prover) -> Response {
+    let segments = request
+        .path
+        .trim_matches('/')
+        .split('/')
+        .collect::<Vec<_>>();
+    let id = |segment: &str| segment.parse::<u64>().ok().and_then(|id| queue.job(id));
+
+    match (request.method.as_str(), &segments[..]) {
+        ("POST", ["jobs"]) => {
+            let request = match serde_json::from_slice::<JobRequest>(&request.body) {
+                Ok(request) => request,
+                Err(e) => return Response::error(400, format!("invalid job: {e}")),
+            };
+            if request.num_instances() > MAX_BATCH_SIZE {
+                return Response::error(
+                    400,
+                    format!("the job has more than {MAX_BATCH_SIZE} instances"),
+                );
+            }
+            if let Err(e) = prover.validate(&request) {
+                return Response::error(400, format!("{e:#}"));
+            }
+            match queue.submit(request) {
+                Ok(id) => Response::json(202, &serde_json::json!({ "id": id })),
+                Err(e) => {
+                    error!("failed to queue a job: {e:#}");
+                    Response::error(500, "failed to queue the job")
+                }
+            }
+        }
+        ("GET", ["jobs"]) => {
+            let jobs = queue.jobs().iter().map(Job::summary).collect::<Vec<_>>();
+            Response::json(200, &jobs)
+        }
+        ("GET", ["jobs", segment]) => match id(segment) {
+            Some(job) => Response::json(200, &job.summary()),
+            None => Response::error(404, "unknown job"),
+        },
+        ("GET", ["jobs", segment, "proof"]) => match id(segment) {
+            Some(job) if job.status == JobStatus::Done => match queue.result(job.id) {
+                Ok(bundle) => Response::json(200, &bundle),
+                Err(e) => Response::error(500, format!("{e:#}")),
+            },
+            Some(job) => Response::error(409, format!("the job is {:?}", job.status)),
+            None => Response::error(404, "unknown job"),
+        },
+        (_, ["jobs", ..]) => Response::error(405, "method not allowed"),
+        _ => Response::error(404, "not found"),
+    }
+}
+
+fn read_request(reader: &mut impl BufRead) -> Result<Request> {
+    let mut line = String::new();
+    reader.read_line(&mut line)?;
+    let mut parts = line.split_whitespace();
+    let (Some(method), Some(path)) = (parts.next(), parts.next()) else {
+        bail!("invalid request line {line:?}");
+    };
+    let (method, path) = (method.to_string(), path.to_string());
+
+    let mut content_length = 0;
+    loop {
+        line.clear();
+        ensure!(
+            reader.read_line(&mut line)? > 0,
+            "unexpected end of headers"
+        );
+        let header = line.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse().context("invalid content length")?;
+            }
+        }
+    }
+    ensure!(
+        content_length <= MAX_BODY_SIZE,
+        "the body exceeds {MAX_BODY_SIZE} bytes"
+    );
+
+    /* the buffer grows with the data actually received, not with the announced length */
+    let mut body = vec![];
+    reader.take(content_length as u64).read_to_end(&mut body)?;
+    ensure!(body.len() == content_length, "the body is truncated");
+    Ok(Request { method, path, body })
+}
+
+fn write_response(mut stream: TcpStream, response: &Response) -> Result<()> {
+    let reason = match response.status {
+        200 => "OK",
+        202 => "Accepted",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        409 => "Conflict",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    };
+    write!(
+        stream,
+        "HTTP/1.1 {} {reason}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        response.status,
+        response.body.len(),
+        response.body
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns an empty bundle for test cases, and fails for signatures.
+    struct FakeProver;
+
+    impl Prover for FakeProver {
+        fn validate(&self, request: &JobRequest) -> Result<()> {
+            match request {
+                JobRequest::Batch { test_case, .. } => TestCase::from_str(test_case).map(|_| ()),
+                JobRequest::Signatures { .. } => Ok(()),
+            }
+        }
+
+        fn prove(&self, request: &JobRequest) -> Result<ProofBundle> {
+            match request {
+                JobRequest::Batch { .. } => Ok(ProofBundle {
+                    proof: "00".to_string(),
+                    jobs: vec![],
+                }),
+                JobRequest::Signatures { .. } => bail!("no keys"),
+            }
+        }
+    }
+
+    fn send(addr: &str, method: &str, path: &str, body: &str) -> (u16, serde_json::Value) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: localhost\r\nContent-Length: {}\r\n\r\n{body}",
+            body.len()
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response[9..12].parse().unwrap();
+        let (_, body) = response.split_once("\r\n\r\n").unwrap();
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    fn wait_for(addr: &str, id: u64, status: &str) {
+        for _ in 0..100 {
+            if send(addr, "GET", &format!("/jobs/{id}"), "").1["status"] == status {
+                return;
+            }
+            thread::sleep(std::time::Duration::from_millis(10));
+        }
+        panic!("job {id} is not {status}");
+    }
+
+    #[test]
+    fn test_server() {
+        let dir = tempfile::tempdir().unwrap();
+        let queue: &'static JobQueue = Box::leak(Box::new(JobQueue::open(dir.path()).unwrap()));
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || serve(listener, queue, &FakeProver));
+
+        let (status, body) = send(&addr, "POST", "/jobs", r#"{"test_case":"test1","batch":2}"#);
+        assert_eq!(status, 202);
+        assert_eq!(body["id"], 1);
+        wait_for(&addr, 1, "done");
+        let (status, body) = send(&addr, "GET", "/jobs/1/proof", "");
+        assert_eq!(status, 200);
+        assert_eq!(body["proof"], "00");
+
+        let (status, _) = send(&addr, "POST", "/jobs", r#"{"signatures":[]}"#);
+        assert_eq!(status, 202);
+        wait_for(&addr, 2, "failed");
+        assert_eq!(send(&addr, "GET", "/jobs/2/proof", "").0, 409);
+
+        assert_eq!(
+            send(&addr, "POST", "/jobs", r#"{"test_case":"test9","batch":1}"#).0,
+            400
+        );
+        assert_eq!(send(&addr, "POST", "/jobs", "{").0, 400);
+        let too_large = format!(r#"{{"test_case":"test1","batch":{}}}"#, MAX_BATCH_SIZE + 1);
+        assert_eq!(send(&addr, "POST", "/jobs", &too_large).0, 400);
+
+        /* a body shorter than announced */
+        let mut stream = TcpStream::connect(&addr).unwrap();
+        write!(
+            stream,
+            "POST /jobs HTTP/1.1\r\nContent-Length: 100\r\n\r\n{{}}"
+        )
+        .unwrap();
+        stream.shutdown(std::net::Shutdown::Write).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 400"), "{response}");
+        assert_eq!(send(&addr, "GET", "/jobs/3", "").0, 404);
+        assert_eq!(
+            send(&addr, "GET", "/jobs", "").1.as_array().unwrap().len(),
+            2
+        );
+
+        /* the jobs survive a restart */
+        let reopened = JobQueue::open(dir.path()).unwrap();
+        assert_eq!(reopened.job(1).unwrap().status, JobStatus::Done);
+        assert_eq!(reopened.job(2).unwrap().status, JobStatus::Failed);
+        assert_eq!(reopened.result(1).unwrap().proof, "00");
+        assert_eq!(
+            reopened
+                .submit(JobRequest::Signatures { signatures: vec![] })
+                .unwrap(),
+            3
+        );
+    }
+}
@@ -0,0 +1,49 @@
+//! Spans of the pipeline stages, with `tracing`.
+//!
+//! Each stage runs in a span named after it, with the test case, the batch size or the number of
+//! constraints as fields:
+//!
+//! | span                       | fields                                         |
+//! |----------------------------|------------------------------------------------|
+//! | `compile`                  | `test_case`, `encoding`, `num_constraints`     |
+//! | `prove`                    | `num_jobs`, `num_instances`                    |
+//! | `witness`                  | `test_case`, `encoding`, `batch_num`           |
+//! | `build_r1cs`               | `test_case`, `encoding`                        |
+//! | `generator`                | `test_case`                                    |
+//! | `parse_file`               | `num_constraints`, `num_lookup_constraints`    |
+//! | `construct_r1cs_from_file` | `optimize`, `num_constraints`                  |
+//! | `repack`                   | `max_len`                                      |
+//! | `optimize`                 | `num_constraints`                              |
+//! | `prove_batch`              | `num_circuits`, `num_instances`                |
+//! | `verify`                   | `num_circuits`, `num_instances`                |
+//!
+//! The spans are only collected once a subscriber is installed, e.g. with [`init_file_collector`].
+//! The rounds of the Varuna prover are not spans, they are timed by `aleo_std_profiler` inside
+//! snarkVM.
+
+use std::fs::File;
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use tracing_subscriber::fmt::format::FmtSpan;
+
+/// Writes the spans to `path` as they close, one JSON object per line with the fields of the span
+/// and of its parents, and its `time.busy` and `time.idle`.
+///
+/// Installs the global subscriber, so it can only be called once. The `log` records are not
+/// routed to it, so that it can be installed next to a logger such as `env_logger`.
+pub fn init_file_collector(path: impl AsRef<Path>) -> Result<()> {
+    let path = path.as_ref();
+    let file = File::create(path).with_context(|| format!("failed to create {path:?}"))?;
+    /* not `try_init`, which also installs a `log` bridge and fails if a logger is set */
+    let subscriber = tracing_subscriber::fmt()
+        .json()
+        .with_span_events(FmtSpan::CLOSE)
+        .with_span_list(true)
+        .with_thread_ids(true)
+        .with_writer(Mutex::new(file))
+        .finish();
+    tracing::subscriber::set_global_default(subscriber)
+        .map_err(|err| anyhow!("failed to install the trace collector: {err}"))
+}
@@ -0,0 +1,47 @@
+//! The `hap` command line, on the fixture circuit.
+
+use std::path::PathBuf;
+use std::process::Command;
+
+use anyhow::Result;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/cube")
+        .join(format!("{name}.cbor"))
+}
+
+#[test]
+fn test_trace() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let trace = dir.path().join("trace.jsonl");
+    let output = Command::new(env!("CARGO_BIN_EXE_hap"))
+        .arg("--trace")
+        .arg(&trace)
+        .arg("check")
+        .arg("--r1cs")
+        .arg(fixture("r1cs"))
+        .arg("--assignment")
+        .arg(fixture("assignment"))
+        .arg("--lookup")
+        .arg(fixture("lookup"))
+        .output()?;
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+
+    let trace = std::fs::read_to_string(trace)?;
+    let lines = trace
+        .lines()
+        .map(serde_json::from_str::<serde_json::Value>)
+        .collect::<Result<Vec<_>, _>>()?;
+    assert!(
+        lines
+            .iter()
+            .any(|line| line["span"]["name"] == "parse_file"),
+        "no parse_file span in the trace"
+    );
+    Ok(())
+}
@@ -0,0 +1,33 @@
+//! The optimization passes on a circuit exported by gnark-circuit-gen.
+
+use anyhow::Result;
+use rand::rngs::OsRng;
+
+use demo::r1cs_provider::{checker, deserialize, gnark, optimize};
+use demo::TestCase;
+
+#[test]
+fn test_optimize_exported_circuit() -> Result<()> {
+    let dir = tempfile::tempdir()?;
+    let output_dir = gnark::generate(TestCase::Test1, None, dir.path())?;
+    let (r1cs, assignment, lookup) = deserialize::parse_file(
+        output_dir.join("r1cs.cbor"),
+        output_dir.join("assignment.cbor"),
+        Some(output_dir.join("lookup.cbor")),
+    )?;
+    assert_eq!(checker::check(&r1cs, &assignment, lookup.as_ref())?, None);
+
+    let optimized = optimize::optimize(&r1cs, lookup.as_ref(), assignment.num_public_inputs)?;
+    assert!(optimized.r1cs.0.len() <= r1cs.0.len());
+
+    let num_accepted = optimize::check_equivalence(
+        &r1cs,
+        lookup.as_ref(),
+        &optimized,
+        &assignment,
+        64,
+        &mut OsRng,
+    )?;
+    assert!(num_accepted >= 1, "the exported assignment is rejected");
+    Ok(())
+}
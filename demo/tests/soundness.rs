@@ -0,0 +1,228 @@
+//! Soundness regressions: a tampered witness, public input or signature must not verify.
+//!
+//! gnark-circuit-gen only runs on valid signatures, and failing to export them fails the test. A
+//! tampered signature is checked against the witness of the valid one: its public inputs replace
+//! those of the exported assignment, and the satisfaction checker must reject the result. A
+//! tampered proof input must be rejected by the verifier. The signatures are secp256k1 ECDSA on
+//! the keccak256 of messages of 100 bytes, the length of the `test1` circuit.
+
+use anyhow::Result;
+use hap_verifier::{encoding::encode_signature, Encoding, SignatureCircuit, VarunaHidingMode};
+use k256::ecdsa::{signature::hazmat::PrehashSigner, Signature, SigningKey};
+use sha3::{Digest, Keccak256};
+use snarkvm_algorithms::polycommit::kzg10::UniversalParams;
+use snarkvm_circuit::Circuit;
+use snarkvm_circuit_environment::prelude::snarkvm_fields::One as _;
+use snarkvm_curves::bls12_377::{Bls12_377, Fr};
+
+use demo::api::{self, ProveJob, ProverConfig};
+use demo::input::SignatureInput;
+use demo::r1cs_provider::deserialize::{self, Assignment, BigInt, Lookup, R1CS};
+use demo::r1cs_provider::{checker, gnark};
+use demo::TestCase;
+
+const MAX_LEN: usize = 100;
+
+fn sign(seed: u8, message: Vec<u8>) -> SignatureInput {
+    let key = SigningKey::from_slice(&[seed; 32]).unwrap();
+    let signature: Signature = key.sign_prehash(&Keccak256::digest(&message)).unwrap();
+    SignatureInput {
+        /* uncompressed SEC1 point without its 0x04 tag */
+        public_key: key.verifying_key().to_encoded_point(false).as_bytes()[1..].to_vec(),
+        message,
+        signature: signature.to_bytes().to_vec(),
+    }
+}
+
+fn valid_signatures() -> Vec<SignatureInput> {
+    (1..=2)
+        .map(|seed| sign(seed, vec![seed; MAX_LEN]))
+        .collect()
+}
+
+fn flip_bit(bytes: &mut [u8], index: usize) {
+    bytes[index / 8] ^= 1 << (index % 8);
+}
+
+/// Tampered copies of `signatures[0]`, named after the mutation.
+fn tampered(signatures: &[SignatureInput]) -> Vec<(&'static str, SignatureInput)> {
+    let valid = &signatures[0];
+    let other = &signatures[1];
+    let mutate = |f: &dyn Fn(&mut SignatureInput)| {
+        let mut signature = valid.clone();
+        f(&mut signature);
+        signature
+    };
+    vec![
+        (
+            "flipped message bit",
+            mutate(&|s| flip_bit(&mut s.message, 3)),
+        ),
+        (
+            "flipped last message bit",
+            mutate(&|s| flip_bit(&mut s.message, 8 * MAX_LEN - 1)),
+        ),
+        ("flipped r bit", mutate(&|s| flip_bit(&mut s.signature, 17))),
+        (
+            "flipped s bit",
+            mutate(&|s| flip_bit(&mut s.signature, 256 + 17)),
+        ),
+        (
+            "flipped public key bit",
+            mutate(&|s| flip_bit(&mut s.public_key, 5)),
+        ),
+        (
+            "public key of another signer",
+            mutate(&|s| s.public_key = other.public_key.clone()),
+        ),
+        (
+            "signature of another message",
+            mutate(&|s| s.signature = other.signature.clone()),
+        ),
+        (
+            "message of another signature",
+            mutate(&|s| s.message = other.message.clone()),
+        ),
+    ]
+}
+
+/// The circuit exported by the generator, with the assignment for the valid `signature`.
+fn generate(signature: &SignatureInput) -> Result<(R1CS, Assignment, Option<Lookup>)> {
+    let dir = tempfile::tempdir()?;
+    let output_dir = gnark::generate(TestCase::Test1, Some(signature), dir.path())?;
+    let (r1cs, assignment, lookup) = deserialize::parse_file(
+        output_dir.join("r1cs.cbor"),
+        output_dir.join("assignment.cbor"),
+        Some(output_dir.join("lookup.cbor")),
+    )?;
+    assert_eq!(checker::check(&r1cs, &assignment, lookup.as_ref())?, None);
+    Ok((r1cs, assignment, lookup))
+}
+
+/// A copy of `assignment` with `variables` instead of its first variables.
+fn replace(assignment: &Assignment, variables: Vec<BigInt>) -> Assignment {
+    let mut tampered = Assignment {
+        variables: assignment.variables.clone(),
+        num_public_inputs: assignment.num_public_inputs,
+        public_encoding: assignment.public_encoding,
+    };
+    tampered.variables.splice(..variables.len(), variables);
+    tampered
+}
+
+fn add_one(variable: &mut BigInt) {
+    *variable = BigInt::from(&(Fr::try_from(&*variable).unwrap() + Fr::one()));
+}
+
+#[test]
+fn test_tampered_signatures() -> Result<()> {
+    let signatures = valid_signatures();
+    let (r1cs, assignment, lookup) = generate(&signatures[0])?;
+    let public_inputs = |signature: &SignatureInput| -> Result<Vec<BigInt>> {
+        let inputs = encode_signature(signature, MAX_LEN, Encoding::Bytes)?;
+        Ok(inputs.iter().map(BigInt::from).collect())
+    };
+
+    /* the exported public inputs are the encoding of the signature */
+    let inputs = public_inputs(&signatures[0])?;
+    assert_eq!(inputs.len(), assignment.num_public_inputs);
+    for (index, input) in inputs.iter().enumerate() {
+        assert_eq!(
+            input.0, assignment.variables[index].0,
+            "public input {index}"
+        );
+    }
+
+    for (mutation, signature) in tampered(&signatures) {
+        let tampered = replace(&assignment, public_inputs(&signature)?);
+        assert!(
+            checker::check(&r1cs, &tampered, lookup.as_ref())?.is_some(),
+            "{mutation} is accepted"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_tampered_assignment() -> Result<()> {
+    let (r1cs, assignment, lookup) = generate(&valid_signatures()[0])?;
+
+    /* the first public input, the last one, the first private variable, one in the middle and
+     * the last one; the variable 0 is the constant 1, which the builder overrides */
+    let num_variables = assignment.variables.len();
+    let num_public = assignment.num_public_inputs;
+    for index in [
+        1,
+        num_public - 1,
+        num_public,
+        num_variables / 2,
+        num_variables - 1,
+    ] {
+        let mut variables = assignment.variables[..=index].to_vec();
+        add_one(&mut variables[index]);
+        let tampered = replace(&assignment, variables);
+        assert!(
+            checker::check(&r1cs, &tampered, lookup.as_ref())?.is_some(),
+            "the assignment with variable {index} changed is accepted"
+        );
+    }
+    Ok(())
+}
+
+#[test]
+fn test_tampered_proof_inputs() -> Result<()> {
+    let urs: UniversalParams<Bls12_377> = api::setup::<VarunaHidingMode>(1000, 1000, 1000)?;
+    let (pk, vk) = api::compile(TestCase::Test1, &urs)?;
+    let config = ProverConfig::default();
+    let fs_parameters = config.fiat_shamir::<Circuit>();
+
+    let signatures = valid_signatures();
+    let job = ProveJob {
+        test_case: TestCase::Test1,
+        pk: &pk,
+        batch_num: signatures.len() as u32,
+        signatures: Some(&signatures),
+        encoding: Encoding::Bytes,
+    };
+    let (proof, inputs) = api::prove_jobs::<Circuit, VarunaHidingMode>(&urs, &[job], &config);
+    let inputs = &inputs[0];
+
+    let circuits = [SignatureCircuit {
+        max_len: MAX_LEN,
+        encoding: Encoding::Bytes,
+        vk: vk.clone(),
+    }];
+    let verify = |signatures: &[SignatureInput]| {
+        hap_verifier::verify_signatures::<VarunaHidingMode>(
+            &urs,
+            fs_parameters,
+            &circuits,
+            &proof,
+            signatures,
+        )
+        .unwrap_or(false)
+    };
+    assert!(verify(&signatures), "the valid signatures must be accepted");
+
+    /* a signature which is not the one proved */
+    for (mutation, signature) in tampered(&signatures) {
+        let tampered = [signature, signatures[1].clone()];
+        assert!(!verify(&tampered), "{mutation} is accepted");
+    }
+    /* the right signatures, in the wrong order or not all of them */
+    assert!(!verify(&[signatures[1].clone(), signatures[0].clone()]));
+    assert!(!verify(&signatures[..1]));
+
+    /* every public input of the first instance, changed in turn */
+    for index in 0..inputs[0].len() {
+        let mut tampered = inputs.clone();
+        tampered[0][index] += Fr::one();
+        let jobs = [(&vk, &tampered[..])];
+        assert!(
+            !api::check_jobs::<VarunaHidingMode>(&urs, fs_parameters, &proof, &jobs)
+                .unwrap_or(false),
+            "the proof is accepted with public input {index} changed"
+        );
+    }
+    Ok(())
+}
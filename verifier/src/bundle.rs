@@ -0,0 +1,95 @@
+//! Proof bundles, and the files of keys and universal parameters.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use snarkvm_algorithms::snark::varuna;
+use snarkvm_curves::bls12_377::{Bls12_377, Fr};
+use snarkvm_utilities::{FromBytes, ToBytes};
+
+/// A proof, with the public inputs of all its instances.
+///
+/// Binary values are hex encoded little endian bytes, as produced by `ToBytes`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProofBundle {
+    pub proof: String,
+    /// One entry per job of the proof, e.g. per message length bucket.
+    pub jobs: Vec<BundleJob>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BundleJob {
+    /// The circuit of the job, e.g. the name of a test case.
+    pub circuit: String,
+    /// The public inputs of each instance.
+    pub inputs: Vec<Vec<String>>,
+}
+
+impl ProofBundle {
+    pub fn new(proof: &varuna::Proof<Bls12_377>, jobs: &[(&str, &[Vec<Fr>])]) -> Result<Self> {
+        Ok(Self {
+            proof: hex::encode(proof.to_bytes_le()?),
+            jobs: jobs
+                .iter()
+                .map(|(circuit, inputs)| -> Result<_> {
+                    Ok(BundleJob {
+                        circuit: circuit.to_string(),
+                        inputs: inputs
+                            .iter()
+                            .map(|instance| {
+                                instance
+                                    .iter()
+                                    .map(|input| Ok(hex::encode(input.to_bytes_le()?)))
+                                    .collect::<Result<Vec<_>>>()
+                            })
+                            .collect::<Result<Vec<_>>>()?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?,
+        })
+    }
+
+    pub fn proof(&self) -> Result<varuna::Proof<Bls12_377>> {
+        from_hex(&self.proof).context("invalid proof")
+    }
+
+    /// The public inputs of each job.
+    pub fn inputs(&self) -> Result<Vec<Vec<Vec<Fr>>>> {
+        self.jobs
+            .iter()
+            .map(|job| {
+                job.inputs
+                    .iter()
+                    .map(|instance| instance.iter().map(|input| from_hex(input)).collect())
+                    .collect()
+            })
+            .collect::<Result<_>>()
+            .context("invalid public input")
+    }
+
+    pub fn read(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file = fs::File::open(path).with_context(|| format!("failed to open {path:?}"))?;
+        serde_json::from_reader(file).with_context(|| format!("failed to parse {path:?}"))
+    }
+
+    pub fn write(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let file = fs::File::create(path).with_context(|| format!("failed to create {path:?}"))?;
+        serde_json::to_writer_pretty(file, self)
+            .with_context(|| format!("failed to write {path:?}"))
+    }
+}
+
+fn from_hex<T: FromBytes>(value: &str) -> Result<T> {
+    T::from_bytes_le(&hex::decode(value)?)
+}
+
+/// Reads a value serialized with `ToBytes`, e.g. keys or universal parameters.
+pub fn read_bytes<T: FromBytes>(path: impl AsRef<Path>) -> Result<T> {
+    let path = path.as_ref();
+    let bytes = fs::read(path).with_context(|| format!("failed to read {path:?}"))?;
+    T::from_bytes_le(&bytes).with_context(|| format!("failed to deserialize {path:?}"))
+}
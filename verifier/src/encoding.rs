@@ -0,0 +1,385 @@
+//! Encoding of signatures into the public inputs of the signature circuits.
+//!
+//! The public inputs of an instance verifying a signature on a message of at most `max_len`
+//! bytes start with the constant `1` of the circuit, followed by the public key, the signature,
+//! the length of the message and the message zero padded to `max_len` bytes. Including the
+//! length keeps a message distinct from its padded versions. There are two layouts:
+//!
+//! - [`Encoding::Bytes`], one field element per byte:
+//!
+//!   ```text
+//!   [1, pk (64 bytes), sig (64 bytes), len, msg (max_len bytes)]
+//!   ```
+//!
+//! - [`Encoding::Packed`], the 256-bit big endian values `pk_x`, `pk_y`, `sig_r` and `sig_s` as
+//!   two 128-bit limbs, least significant first, and the message as chunks of 31 bytes, each
+//!   read as a little endian integer (the last chunk is zero padded):
+//!
+//!   ```text
+//!   [1, pk_x.lo, pk_x.hi, pk_y.lo, pk_y.hi, r.lo, r.hi, s.lo, s.hi, len, msg[0..31], msg[31..62], ...]
+//!   ```
+//!
+//!   A 50,000 byte message takes 1,613 field elements instead of 50,000.
+//!
+//! The exchange format declares the layout of an assignment in its `public_encoding` field, and
+//! the prover checks that the public inputs of its circuits match the encoding of the signature,
+//! so a verifier can compute them from the signatures instead of trusting the inputs of a
+//! [`ProofBundle`].
+//!
+//! [`ProofBundle`]: crate::ProofBundle
+
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Result};
+use serde::{Deserialize, Serialize};
+use snarkvm_curves::bls12_377::Fr;
+use snarkvm_utilities::ToBytes;
+
+use crate::input::SignatureInput;
+use crate::VerifyingKey;
+
+/// Number of message bytes per field element in [`Encoding::Packed`].
+pub const CHUNK_SIZE: usize = 31;
+
+/// Number of bytes per limb of a 256-bit value in [`Encoding::Packed`].
+pub const LIMB_SIZE: usize = 16;
+
+/// Layout of the public inputs, see the [module documentation](self).
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Encoding {
+    #[default]
+    Bytes,
+    Packed,
+}
+
+impl Encoding {
+    pub const ALL: [Encoding; 2] = [Encoding::Bytes, Encoding::Packed];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Bytes => "bytes",
+            Encoding::Packed => "packed",
+        }
+    }
+
+    /// Number of public inputs, including the constant `1`, for messages of `max_len` bytes.
+    pub fn num_public_inputs(&self, max_len: usize) -> usize {
+        match self {
+            Encoding::Bytes => 1 + 64 + 64 + 1 + max_len,
+            Encoding::Packed => 1 + 8 + 1 + max_len.div_ceil(CHUNK_SIZE),
+        }
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match Encoding::ALL.iter().find(|encoding| encoding.name() == s) {
+            Some(encoding) => Ok(*encoding),
+            None => bail!("unknown encoding {s:?}, expected bytes or packed"),
+        }
+    }
+}
+
+/// The public inputs of the instance verifying `signature`, in a circuit for messages of at most
+/// `max_len` bytes.
+pub fn encode_signature(
+    signature: &SignatureInput,
+    max_len: usize,
+    encoding: Encoding,
+) -> Result<Vec<Fr>> {
+    signature.validate()?;
+    let len = signature.message.len();
+    ensure!(
+        len <= max_len,
+        "the message of {len} bytes does not fit in a circuit for {max_len} bytes"
+    );
+
+    let mut message = signature.message.clone();
+    message.resize(max_len, 0);
+
+    let mut inputs = Vec::with_capacity(encoding.num_public_inputs(max_len));
+    inputs.push(Fr::from(1u64));
+    match encoding {
+        Encoding::Bytes => {
+            let bytes = |bytes: &[u8]| {
+                bytes
+                    .iter()
+                    .map(|byte| Fr::from(*byte as u64))
+                    .collect::<Vec<_>>()
+            };
+            inputs.extend(bytes(&signature.public_key));
+            inputs.extend(bytes(&signature.signature));
+            inputs.push(Fr::from(len as u64));
+            inputs.extend(bytes(&message));
+        }
+        Encoding::Packed => {
+            for value in [&signature.public_key, &signature.signature] {
+                for coordinate in value.chunks(32) {
+                    let (hi, lo) = coordinate.split_at(LIMB_SIZE);
+                    inputs.push(pack_be(lo));
+                    inputs.push(pack_be(hi));
+                }
+            }
+            inputs.push(Fr::from(len as u64));
+            inputs.extend(message.chunks(CHUNK_SIZE).map(pack_le));
+        }
+    }
+    Ok(inputs)
+}
+
+/// The signature encoded in `inputs`, the inverse of [`encode_signature`]. Fails if the inputs
+/// are not a canonical encoding, e.g. if the padding of the message is not zero.
+pub fn decode_signature(
+    inputs: &[Fr],
+    max_len: usize,
+    encoding: Encoding,
+) -> Result<SignatureInput> {
+    let num_public_inputs = encoding.num_public_inputs(max_len);
+    ensure!(
+        inputs.len() == num_public_inputs,
+        "expected {num_public_inputs} public inputs, got {}",
+        inputs.len()
+    );
+    ensure!(
+        inputs[0] == Fr::from(1u64),
+        "the first public input is not 1"
+    );
+
+    let (public_key, signature, len, message) = match encoding {
+        Encoding::Bytes => {
+            let bytes = |inputs: &[Fr]| {
+                inputs
+                    .iter()
+                    .map(|input| Ok(unpack_le(input, 1)?[0]))
+                    .collect::<Result<Vec<_>>>()
+            };
+            (
+                bytes(&inputs[1..65])?,
+                bytes(&inputs[65..129])?,
+                &inputs[129],
+                bytes(&inputs[130..])?,
+            )
+        }
+        Encoding::Packed => {
+            let mut coordinates = vec![];
+            for limbs in inputs[1..9].chunks(2) {
+                /* big endian: the high limb first, each limb reversed */
+                let mut coordinate = unpack_le(&limbs[1], LIMB_SIZE)?;
+                coordinate.reverse();
+                let mut lo = unpack_le(&limbs[0], LIMB_SIZE)?;
+                lo.reverse();
+                coordinate.extend(lo);
+                coordinates.push(coordinate);
+            }
+            let mut message = vec![];
+            for chunk in &inputs[10..] {
+                message.extend(unpack_le(chunk, CHUNK_SIZE)?);
+            }
+            /* the last chunk is padded past `max_len` too */
+            ensure!(
+                message[max_len..].iter().all(|byte| *byte == 0),
+                "the message is not zero padded"
+            );
+            message.truncate(max_len);
+            (
+                coordinates[..2].concat(),
+                coordinates[2..].concat(),
+                &inputs[9],
+                message,
+            )
+        }
+    };
+
+    let len = unpack_le(len, 8)?;
+    let len = u64::from_le_bytes(len.try_into().expect("8 bytes")) as usize;
+    ensure!(len <= max_len, "the message length {len} exceeds {max_len}");
+    ensure!(
+        message[len..].iter().all(|byte| *byte == 0),
+        "the message is not zero padded"
+    );
+    Ok(SignatureInput {
+        public_key,
+        message: message[..len].to_vec(),
+        signature,
+    })
+}
+
+/// The public inputs of [`Encoding::Packed`] as linear combinations of the public inputs of
+/// [`Encoding::Bytes`], in a circuit for messages of at most `max_len` bytes.
+///
+/// The combination at index `i` computes the public input `i + 1`, after the constant `1`, as
+/// terms `(index, coefficient)` over the byte encoded inputs. They only hold for byte values
+/// below 256, which a circuit packing its inputs has to enforce.
+pub fn packed_from_bytes(max_len: usize) -> Vec<Vec<(usize, Fr)>> {
+    let powers = (0..CHUNK_SIZE)
+        .scan(Fr::from(1u64), |power, _| {
+            let current = *power;
+            *power *= Fr::from(256u64);
+            Some(current)
+        })
+        .collect::<Vec<_>>();
+    /* the big endian bytes `start..start + LIMB_SIZE` */
+    let limb = |start: usize| {
+        (0..LIMB_SIZE)
+            .map(|k| (start + k, powers[LIMB_SIZE - 1 - k]))
+            .collect::<Vec<_>>()
+    };
+
+    let mut packed = Vec::with_capacity(Encoding::Packed.num_public_inputs(max_len) - 1);
+    /* pk_x, pk_y, r and s start at the inputs 1, 33, 65 and 97 */
+    for coordinate in (1..129).step_by(32) {
+        packed.push(limb(coordinate + LIMB_SIZE));
+        packed.push(limb(coordinate));
+    }
+    packed.push(vec![(129, Fr::from(1u64))]);
+    for chunk in (130..130 + max_len).step_by(CHUNK_SIZE) {
+        let end = (chunk + CHUNK_SIZE).min(130 + max_len);
+        packed.push((chunk..end).zip(powers.iter().copied()).collect());
+    }
+    packed
+}
+
+/// The field element of the little endian integer `bytes`, of at most 31 bytes.
+fn pack_le(bytes: &[u8]) -> Fr {
+    pack_be(&bytes.iter().rev().copied().collect::<Vec<_>>())
+}
+
+/// The field element of the big endian integer `bytes`, of at most 31 bytes.
+fn pack_be(bytes: &[u8]) -> Fr {
+    debug_assert!(bytes.len() <= CHUNK_SIZE);
+    bytes.iter().fold(Fr::from(0u64), |acc, byte| {
+        acc * Fr::from(256u64) + Fr::from(*byte as u64)
+    })
+}
+
+/// The `len` low bytes of `input` in little endian, failing if `input` does not fit.
+fn unpack_le(input: &Fr, len: usize) -> Result<Vec<u8>> {
+    let bytes = input.to_bytes_le()?;
+    ensure!(
+        bytes[len..].iter().all(|byte| *byte == 0),
+        "public input does not fit in {len} bytes"
+    );
+    Ok(bytes[..len].to_vec())
+}
+
+/// A signature circuit for messages of at most `max_len` bytes.
+#[derive(Debug, Clone)]
+pub struct SignatureCircuit {
+    pub max_len: usize,
+    pub encoding: Encoding,
+    pub vk: VerifyingKey,
+}
+
+/// Groups the signatures into jobs as the prover does: each signature goes to the smallest
+/// circuit its message fits in, and the jobs are ordered by increasing message length.
+///
+/// Returns the verifying key and the public inputs of each job.
+pub fn encode_jobs<'a>(
+    circuits: &'a [SignatureCircuit],
+    signatures: &[SignatureInput],
+) -> Result<Vec<(&'a VerifyingKey, Vec<Vec<Fr>>)>> {
+    let mut jobs = BTreeMap::<usize, (&VerifyingKey, Vec<Vec<Fr>>)>::new();
+    for (index, signature) in signatures.iter().enumerate() {
+        let len = signature.message.len();
+        let circuit = circuits
+            .iter()
+            .filter(|circuit| len <= circuit.max_len)
+            .min_by_key(|circuit| circuit.max_len);
+        let Some(circuit) = circuit else {
+            bail!("no circuit for the message of {len} bytes of signature {index}");
+        };
+        jobs.entry(circuit.max_len)
+            .or_insert_with(|| (&circuit.vk, vec![]))
+            .1
+            .push(encode_signature(
+                signature,
+                circuit.max_len,
+                circuit.encoding,
+            )?);
+    }
+    Ok(jobs.into_values().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signature() -> SignatureInput {
+        SignatureInput {
+            public_key: (0..64).collect(),
+            message: (100..140).collect(),
+            signature: (64..128).collect(),
+        }
+    }
+
+    #[test]
+    fn test_encode_bytes() -> Result<()> {
+        let signature = signature();
+        let inputs = encode_signature(&signature, 64, Encoding::Bytes)?;
+        assert_eq!(inputs.len(), Encoding::Bytes.num_public_inputs(64));
+        assert_eq!(inputs[0], Fr::from(1u64));
+        assert_eq!(inputs[64], Fr::from(63u64));
+        assert_eq!(inputs[65], Fr::from(64u64));
+        assert_eq!(inputs[129], Fr::from(40u64));
+        assert_eq!(inputs[130], Fr::from(100u64));
+        assert_eq!(inputs[193], Fr::from(0u64));
+        assert_eq!(decode_signature(&inputs, 64, Encoding::Bytes)?, signature);
+
+        assert!(encode_signature(&signature, 39, Encoding::Bytes).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_encode_packed() -> Result<()> {
+        let signature = signature();
+        let inputs = encode_signature(&signature, 64, Encoding::Packed)?;
+        assert_eq!(inputs.len(), 1 + 8 + 1 + 3);
+        assert_eq!(inputs.len(), Encoding::Packed.num_public_inputs(64));
+
+        /* the low limb of pk_x is its last 16 bytes */
+        assert_eq!(inputs[1], pack_be(&(16..32).collect::<Vec<_>>()));
+        assert_eq!(inputs[9], Fr::from(40u64));
+        /* the first chunk starts with the first byte of the message */
+        assert_eq!(unpack_le(&inputs[10], CHUNK_SIZE)?[0], 100);
+        assert_eq!(decode_signature(&inputs, 64, Encoding::Packed)?, signature);
+
+        /* the padding must be zero */
+        let mut tampered = inputs.clone();
+        tampered[12] = Fr::from(1u64);
+        assert!(decode_signature(&tampered, 64, Encoding::Packed).is_err());
+        /* also past `max_len`, in the padding of the last chunk */
+        let mut chunk = unpack_le(&inputs[12], CHUNK_SIZE)?;
+        chunk[70 - 2 * CHUNK_SIZE] = 1;
+        tampered[12] = pack_le(&chunk);
+        assert!(decode_signature(&tampered, 64, Encoding::Packed).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_packed_from_bytes() -> Result<()> {
+        let signature = signature();
+        for max_len in [40, 62, 64] {
+            let bytes = encode_signature(&signature, max_len, Encoding::Bytes)?;
+            let packed = packed_from_bytes(max_len)
+                .iter()
+                .map(|terms| {
+                    terms
+                        .iter()
+                        .map(|(index, coeff)| bytes[*index] * coeff)
+                        .sum::<Fr>()
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(
+                packed,
+                encode_signature(&signature, max_len, Encoding::Packed)?[1..]
+            );
+        }
+        Ok(())
+    }
+}
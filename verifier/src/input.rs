@@ -0,0 +1,33 @@
+//! Signatures as passed by a verifier.
+
+use anyhow::{ensure, Result};
+use serde::{Deserialize, Serialize};
+
+/// A tuple `(public key, msg, signature)` as passed by a verifier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureInput {
+    /// Uncompressed public key `x || y`, 64 bytes.
+    #[serde(with = "hex::serde")]
+    pub public_key: Vec<u8>,
+    #[serde(with = "hex::serde")]
+    pub message: Vec<u8>,
+    /// Signature `r || s`, 64 bytes.
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
+}
+
+impl SignatureInput {
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.public_key.len() == 64,
+            "public key must be 64 bytes, got {}",
+            self.public_key.len()
+        );
+        ensure!(
+            self.signature.len() == 64,
+            "signature must be 64 bytes, got {}",
+            self.signature.len()
+        );
+        Ok(())
+    }
+}
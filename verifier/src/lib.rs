@@ -0,0 +1,139 @@
+//! Verifier of the batch proofs produced by the `demo` crate.
+//!
+//! This crate only depends on the Varuna verifier of snarkVM, so that proofs can be verified in
+//! services which do not build circuits. The prover writes a [`ProofBundle`] with the proof and
+//! the encoded public inputs of every instance; verifying it only requires the universal
+//! parameters and the verifying key of each circuit.
+//!
+//! With [`verify_signatures`], the public inputs are computed from the signatures themselves, see
+//! [`encoding`], so the verifier does not rely on the encoding done by the prover.
+//!
+//! The verifier must use the proof system of the prover: the functions are generic over the
+//! hiding mode `SM` of Varuna, [`VarunaHidingMode`] for zero knowledge proofs or
+//! [`VarunaNonHidingMode`], and take the Fiat-Shamir parameters of the network, e.g.
+//! [`fs_parameters`] for Testnet3.
+
+use std::collections::BTreeMap;
+
+use anyhow::{bail, Result};
+use once_cell::sync::OnceCell;
+use snarkvm_algorithms::{
+    crypto_hash::PoseidonSponge,
+    polycommit::kzg10::UniversalParams,
+    snark::varuna::{self, CircuitVerifyingKey, SNARKMode},
+    traits::{AlgebraicSponge, SNARK},
+};
+use snarkvm_curves::bls12_377::{Bls12_377, Fq, Fr};
+
+pub mod bundle;
+pub mod encoding;
+pub mod input;
+
+pub use bundle::{read_bytes, BundleJob, ProofBundle};
+pub use encoding::{Encoding, SignatureCircuit};
+pub use input::SignatureInput;
+pub use snarkvm_algorithms::snark::varuna::{VarunaHidingMode, VarunaNonHidingMode};
+
+//
+// Aliases
+// =======
+//
+
+pub type FS = PoseidonSponge<Fq, 2, 1>;
+pub type FsParameters = <FS as AlgebraicSponge<Fq, 2>>::Parameters;
+pub type VarunaInst<SM = VarunaHidingMode> = varuna::VarunaSNARK<Bls12_377, FS, SM>;
+pub type VerifyingKey = CircuitVerifyingKey<Bls12_377>;
+
+/// The Fiat-Shamir parameters of Testnet3, sampled as `Testnet3::varuna_fs_parameters()` does.
+pub fn fs_parameters() -> &'static FsParameters {
+    static PARAMETERS: OnceCell<FsParameters> = OnceCell::new();
+    PARAMETERS.get_or_init(FS::sample_parameters)
+}
+
+/// Verifies a batch proof, given the public inputs of the instances of each circuit.
+pub fn verify_batch<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    proof: &varuna::Proof<Bls12_377>,
+    vks_to_inputs: &BTreeMap<&VerifyingKey, &[Vec<Fr>]>,
+) -> Result<bool> {
+    let universal_verifier = urs.to_universal_verifier()?;
+    Ok(VarunaInst::<SM>::verify_batch(
+        &universal_verifier,
+        fs_parameters,
+        vks_to_inputs,
+        proof,
+    )?)
+}
+
+/// Verifies a batch proof made of several jobs, given the verifying key and the public inputs
+/// of each job, in the order of the jobs. Jobs may share a verifying key.
+pub fn verify_jobs<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    proof: &varuna::Proof<Bls12_377>,
+    jobs: &[(&VerifyingKey, &[Vec<Fr>])],
+) -> Result<bool> {
+    let mut inputs = BTreeMap::<_, Vec<_>>::new();
+    for (vk, job_inputs) in jobs {
+        inputs.entry(*vk).or_default().extend_from_slice(job_inputs);
+    }
+    let vks_to_inputs = inputs
+        .iter()
+        .map(|(vk, inputs)| (*vk, &inputs[..]))
+        .collect::<BTreeMap<_, _>>();
+
+    verify_batch::<SM>(urs, fs_parameters, proof, &vks_to_inputs)
+}
+
+/// Verifies a proof bundle whose jobs all use the circuit of `vk`.
+pub fn verify_proof<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    vk: &VerifyingKey,
+    bundle: &ProofBundle,
+) -> Result<bool> {
+    let proof = bundle.proof()?;
+    let inputs = bundle.inputs()?;
+    let jobs = inputs
+        .iter()
+        .map(|inputs| (vk, &inputs[..]))
+        .collect::<Vec<_>>();
+    verify_jobs::<SM>(urs, fs_parameters, &proof, &jobs)
+}
+
+/// Verifies a proof bundle over several circuits, with the verifying keys by circuit name.
+pub fn verify_bundle<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    vks: &BTreeMap<String, VerifyingKey>,
+    bundle: &ProofBundle,
+) -> Result<bool> {
+    let proof = bundle.proof()?;
+    let inputs = bundle.inputs()?;
+    let mut jobs = Vec::with_capacity(inputs.len());
+    for (job, inputs) in bundle.jobs.iter().zip(&inputs) {
+        let Some(vk) = vks.get(&job.circuit) else {
+            bail!("no verifying key for circuit {:?}", job.circuit);
+        };
+        jobs.push((vk, &inputs[..]));
+    }
+    verify_jobs::<SM>(urs, fs_parameters, &proof, &jobs)
+}
+
+/// Verifies a proof of `signatures` produced with message length buckets, computing the public
+/// inputs from the signatures, see [`encoding`].
+pub fn verify_signatures<SM: SNARKMode>(
+    urs: &UniversalParams<Bls12_377>,
+    fs_parameters: &FsParameters,
+    circuits: &[SignatureCircuit],
+    proof: &varuna::Proof<Bls12_377>,
+    signatures: &[SignatureInput],
+) -> Result<bool> {
+    let jobs = encoding::encode_jobs(circuits, signatures)?;
+    let jobs = jobs
+        .iter()
+        .map(|(vk, inputs)| (*vk, &inputs[..]))
+        .collect::<Vec<_>>();
+    verify_jobs::<SM>(urs, fs_parameters, proof, &jobs)
+}